@@ -0,0 +1,199 @@
+use crate::structs::color::{Color, srgb_to_linear, linear_to_srgb};
+
+// Temperatures are in Kelvin throughout. The locus is Kim et al.'s cubic
+// spline fit of the CIE 1931 Planckian locus, which holds from 1667K to
+// 25000K. Below that the fit folds back on itself, so candle light down to
+// MIN_KELVIN uses Krystek's rational fit instead.
+pub const MIN_KELVIN: f64 = 1000.0;
+pub const MAX_KELVIN: f64 = 25000.0;
+
+pub fn kelvin_to_mired(kelvin: f64) -> f64{
+    return 1_000_000.0 / kelvin.max(1.0);
+}
+pub fn mired_to_kelvin(mired: f64) -> f64{
+    return 1_000_000.0 / mired.max(1.0);
+}
+
+pub fn planckian_xy(kelvin: f64) -> (f64, f64){
+    let t = kelvin.clamp(MIN_KELVIN, MAX_KELVIN);
+    if t < 1667.0{
+        let t2 = t * t;
+        let u = (0.860117757 + 1.54118254e-4 * t + 1.28641212e-7 * t2) / (1.0 + 8.42420235e-4 * t + 7.08145163e-7 * t2);
+        let v = (0.317398726 + 4.22806245e-5 * t + 4.20481691e-8 * t2) / (1.0 - 2.89741816e-5 * t + 1.61456053e-7 * t2);
+        return uv_to_xy(u, v);
+    }
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0{
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    }else{
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0{
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    }else if t <= 4000.0{
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    }else{
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    return (x, y);
+}
+
+// CIE 1960 uv, the space Duv is measured in
+fn xy_to_uv(x: f64, y: f64) -> (f64, f64){
+    let d = -2.0 * x + 12.0 * y + 3.0;
+    return (4.0 * x / d, 6.0 * y / d);
+}
+fn uv_to_xy(u: f64, v: f64) -> (f64, f64){
+    let d = 2.0 * u - 8.0 * v + 4.0;
+    return (3.0 * u / d, 2.0 * v / d);
+}
+
+// Unit normal to the locus in uv, pointing to positive Duv (towards green)
+fn locus_normal(kelvin: f64) -> (f64, f64){
+    let mired = kelvin_to_mired(kelvin);
+    let (u1, v1) = planckian_uv(mired_to_kelvin(mired + 0.5));
+    let (u2, v2) = planckian_uv(mired_to_kelvin(mired - 0.5));
+    let (du, dv) = (u2 - u1, v2 - v1);
+    let length = (du * du + dv * dv).sqrt().max(f64::EPSILON);
+    return (dv / length, -du / length);
+}
+fn planckian_uv(kelvin: f64) -> (f64, f64){
+    let (x, y) = planckian_xy(kelvin);
+    return xy_to_uv(x, y);
+}
+
+pub fn cct_to_xy(kelvin: f64, duv: f64) -> (f64, f64){
+    let (u, v) = planckian_uv(kelvin);
+    if duv == 0.0{
+        return uv_to_xy(u, v);
+    }
+    let (nu, nv) = locus_normal(kelvin);
+    return uv_to_xy(u + nu * duv, v + nv * duv);
+}
+
+const COARSE_MIRED: f64 = 10.0;
+
+// Correlated color temperature and Duv of a chromaticity, found by searching
+// the locus in mired, where it is close to evenly spaced
+pub fn xy_to_cct(x: f64, y: f64) -> (f64, f64){
+    let (u, v) = xy_to_uv(x, y);
+    let distance = |mired: f64| -> f64{
+        let (lu, lv) = planckian_uv(mired_to_kelvin(mired));
+        return (lu - u).powi(2) + (lv - v).powi(2);
+    };
+    let (low, high) = (kelvin_to_mired(MAX_KELVIN), kelvin_to_mired(MIN_KELVIN));
+    // A coarse scan finds the right stretch of the locus, then it is narrowed down
+    let mut best = (low, distance(low));
+    let mut mired = low + COARSE_MIRED;
+    while mired <= high{
+        let d = distance(mired);
+        if d < best.1{
+            best = (mired, d);
+        }
+        mired += COARSE_MIRED;
+    }
+    let (mut a, mut b) = ((best.0 - COARSE_MIRED).max(low), (best.0 + COARSE_MIRED).min(high));
+    while b - a > 0.01{
+        let (m1, m2) = (a + (b - a) / 3.0, b - (b - a) / 3.0);
+        if distance(m1) < distance(m2){
+            b = m2;
+        }else{
+            a = m1;
+        }
+    }
+    let kelvin = mired_to_kelvin((a + b) / 2.0);
+    let (lu, lv) = planckian_uv(kelvin);
+    let (nu, nv) = locus_normal(kelvin);
+    return (kelvin, (u - lu) * nu + (v - lv) * nv);
+}
+
+const XYZ_TO_RGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252]
+];
+const RGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041]
+];
+
+// The brightest sRGB color with this chromaticity, out of gamut parts are clipped
+pub fn xy_to_color(x: f64, y: f64) -> Color{
+    let y = y.max(f64::EPSILON);
+    let xyz = [x / y, 1.0, (1.0 - x - y) / y];
+    let mut rgb = [0.0; 3];
+    for (out, row) in rgb.iter_mut().zip(XYZ_TO_RGB.iter()){
+        *out = (row[0] * xyz[0] + row[1] * xyz[1] + row[2] * xyz[2]).max(0.0);
+    }
+    let max = rgb.iter().cloned().fold(f64::EPSILON, f64::max);
+    let c = |x: f64| (linear_to_srgb((x / max) as f32) * 255.0).round().clamp(0.0, 255.0) as u8;
+    return Color::new(c(rgb[0]), c(rgb[1]), c(rgb[2]));
+}
+pub fn color_to_xy(color: Color) -> Option<(f64, f64)>{
+    let rgb = color.to_bytes().map(|x| srgb_to_linear(x as f32 / 255.0) as f64);
+    let mut xyz = [0.0; 3];
+    for (out, row) in xyz.iter_mut().zip(RGB_TO_XYZ.iter()){
+        *out = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+    }
+    let sum = xyz[0] + xyz[1] + xyz[2];
+    if sum <= 0.0{
+        return None;
+    }
+    return Some((xyz[0] / sum, xyz[1] / sum));
+}
+
+pub fn cct_to_color(kelvin: f64, duv: f64) -> Color{
+    let (x, y) = cct_to_xy(kelvin, duv);
+    return xy_to_color(x, y);
+}
+pub fn color_to_cct(color: Color) -> Option<(f64, f64)>{
+    let (x, y) = color_to_xy(color)?;
+    return Some(xy_to_cct(x, y));
+}
+
+// Full brightness color of a temperature on the locus
+pub fn kelvin_to_color(kelvin: u32) -> Color{
+    return cct_to_color(kelvin as f64, 0.0);
+}
+// Nearest temperature to a color, 0 for black
+pub fn color_to_kelvin(color: Color) -> u32{
+    return match color_to_cct(color){
+        None => 0,
+        Some((kelvin, _)) => kelvin.round() as u32
+    };
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn locus_round_trips(){
+        for kelvin in [1200.0, 1700.0, 2700.0, 4000.0, 6500.0, 10000.0, 20000.0]{
+            for duv in [-0.01, 0.0, 0.01]{
+                let (x, y) = cct_to_xy(kelvin, duv);
+                let (k, d) = xy_to_cct(x, y);
+                assert!((k - kelvin).abs() / kelvin < 0.001, "{} came back as {}", kelvin, k);
+                assert!((d - duv).abs() < 0.0005, "Duv {} came back as {}", duv, d);
+            }
+        }
+    }
+
+    #[test]
+    fn d65_is_near_6500k(){
+        let (k, duv) = xy_to_cct(0.31271, 0.32902);
+        assert!((k - 6504.0).abs() < 30.0, "{}", k);
+        assert!(duv > 0.0 && duv < 0.005);
+    }
+
+    #[test]
+    fn colors_map_to_nearby_temperatures(){
+        assert_eq!(color_to_kelvin(Color::new(0, 0, 0)), 0);
+        for kelvin in [2700, 4000, 6500]{
+            let k = color_to_kelvin(kelvin_to_color(kelvin));
+            assert!((k as i32 - kelvin as i32).abs() < 50, "{} came back as {}", kelvin, k);
+        }
+    }
+}
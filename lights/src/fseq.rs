@@ -0,0 +1,526 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::time::Instant;
+
+use crate::structs::color::Color;
+use crate::structs::light_primitive::*;
+use crate::structs::profile::*;
+
+fn invalid(message: &str) -> io::Error{
+    return io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+}
+fn read_u16(data: &[u8], offset: usize) -> u16{
+    return u16::from_le_bytes([data[offset], data[offset + 1]]);
+}
+fn read_u24(data: &[u8], offset: usize) -> u32{
+    return u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], 0]);
+}
+fn read_u32(data: &[u8], offset: usize) -> u32{
+    return u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FseqCompression{
+    None,
+    Zstd,
+    Zlib
+}
+
+#[derive(Debug, Clone)]
+struct CompressionBlock{
+    first_frame: u32,
+    offset: usize,
+    size: usize
+}
+
+pub struct FseqFile{
+    version: (u8, u8),
+    channel_count: u32,
+    stored_channels: u32,
+    frame_count: u32,
+    step_ms: u8,
+    compression: FseqCompression,
+    blocks: Vec<CompressionBlock>,
+    sparse_ranges: Vec<(u32, u32)>,
+    data: Vec<u8>,
+    data_offset: usize,
+    cache: RefCell<Option<(usize, Vec<u8>)>>
+}
+
+impl FseqFile{
+    pub fn open(path: &str) -> io::Result<FseqFile>{
+        return FseqFile::parse(fs::read(path)?);
+    }
+
+    pub fn parse(data: Vec<u8>) -> io::Result<FseqFile>{
+        if data.len() < 28 || (&data[0..4] != b"PSEQ" && &data[0..4] != b"FSEQ"){
+            return Err(invalid("Not an fseq file"));
+        }
+        let data_offset = read_u16(&data, 4) as usize;
+        let minor = data[6];
+        let major = data[7];
+        let stored_channels = read_u32(&data, 10);
+        let frame_count = read_u32(&data, 14);
+        let step_ms = data[18];
+
+        let mut out = FseqFile {
+            version: (major, minor),
+            channel_count: stored_channels,
+            stored_channels,
+            frame_count,
+            step_ms,
+            compression: FseqCompression::None,
+            blocks: Vec::new(),
+            sparse_ranges: Vec::new(),
+            data_offset,
+            data: Vec::new(),
+            cache: RefCell::new(None)
+        };
+
+        match major{
+            1 => (),
+            2 => {
+                if data.len() < 32{
+                    return Err(invalid("Truncated fseq v2 header"));
+                }
+                out.compression = match data[20] & 0x0F{
+                    0 => FseqCompression::None,
+                    1 => FseqCompression::Zstd,
+                    2 => FseqCompression::Zlib,
+                    _ => return Err(invalid("Unknown fseq compression type"))
+                };
+                let block_count = (((data[20] & 0xF0) as usize) << 4) | data[21] as usize;
+                let sparse_count = data[22] as usize;
+
+                let mut index = 32;
+                let mut offset = data_offset;
+                for _ in 0..block_count{
+                    if index + 8 > data.len(){
+                        return Err(invalid("Truncated fseq block index"));
+                    }
+                    let first_frame = read_u32(&data, index);
+                    let size = read_u32(&data, index + 4) as usize;
+                    // Writers pad the index with empty blocks
+                    if size > 0{
+                        out.blocks.push(CompressionBlock { first_frame, offset, size });
+                    }
+                    offset += size;
+                    index += 8;
+                }
+                for _ in 0..sparse_count{
+                    if index + 6 > data.len(){
+                        return Err(invalid("Truncated fseq sparse ranges"));
+                    }
+                    out.sparse_ranges.push((read_u24(&data, index), read_u24(&data, index + 3)));
+                    index += 6;
+                }
+                let sparse_total: u64 = out.sparse_ranges.iter().map(|(_, c)| *c as u64).sum();
+                if sparse_total > stored_channels as u64{
+                    return Err(invalid("fseq sparse ranges hold more channels than are stored"));
+                }
+                if !out.sparse_ranges.is_empty(){
+                    out.channel_count = out.sparse_ranges.iter().map(|(s, c)| s + c).max().unwrap_or(0);
+                }
+            },
+            _ => return Err(invalid("Unsupported fseq version"))
+        }
+
+        if out.compression == FseqCompression::None{
+            let needed = data_offset + stored_channels as usize * frame_count as usize;
+            if data.len() < needed{
+                return Err(invalid("Truncated fseq channel data"));
+            }
+        }
+        out.data = data;
+        return Ok(out);
+    }
+
+    pub fn get_version(&self) -> (u8, u8){
+        return self.version;
+    }
+    pub fn get_channel_count(&self) -> u32{
+        return self.channel_count;
+    }
+    pub fn get_frame_count(&self) -> u32{
+        return self.frame_count;
+    }
+    pub fn get_step_ms(&self) -> u8{
+        return self.step_ms;
+    }
+    pub fn get_compression(&self) -> FseqCompression{
+        return self.compression;
+    }
+
+    fn decompress_block(&self, block: &CompressionBlock) -> io::Result<Vec<u8>>{
+        let end = (block.offset + block.size).min(self.data.len());
+        let compressed = &self.data[block.offset.min(end)..end];
+        let mut out: Vec<u8> = Vec::new();
+        match self.compression{
+            FseqCompression::Zstd => {
+                let mut decoder = match ruzstd::StreamingDecoder::new(compressed){
+                    Ok(x) => x,
+                    Err(e) => return Err(invalid(&e.to_string()))
+                };
+                decoder.read_to_end(&mut out)?;
+            },
+            FseqCompression::Zlib => return Err(invalid("zlib compressed fseq files are not supported")),
+            FseqCompression::None => out.extend_from_slice(compressed)
+        }
+        return Ok(out);
+    }
+
+    // The channels stored for a frame, only the sparse ranges if there are any
+    fn stored_frame(&self, frame: u32) -> io::Result<Vec<u8>>{
+        let size = self.stored_channels as usize;
+        if self.compression == FseqCompression::None{
+            let start = self.data_offset + frame as usize * size;
+            return Ok(self.data[start..start + size].to_vec());
+        }
+
+        let index = match self.blocks.iter().rposition(|x| x.first_frame <= frame){
+            None => return Err(invalid("Frame is not in any compression block")),
+            Some(x) => x
+        };
+        let mut cache = self.cache.borrow_mut();
+        let cached = match cache.as_ref(){
+            Some((i, _)) => *i == index,
+            None => false
+        };
+        if !cached{
+            *cache = Some((index, self.decompress_block(&self.blocks[index])?));
+        }
+        let decoded = &cache.as_ref().unwrap().1;
+
+        let start = (frame - self.blocks[index].first_frame) as usize * size;
+        if start + size > decoded.len(){
+            return Err(invalid("Compression block is shorter than expected"));
+        }
+        return Ok(decoded[start..start + size].to_vec());
+    }
+
+    // All channels of a frame, channels outside the sparse ranges are zero
+    pub fn get_frame(&self, frame: u32) -> io::Result<Vec<u8>>{
+        if frame >= self.frame_count{
+            return Err(invalid("Frame out of range"));
+        }
+        let stored = self.stored_frame(frame)?;
+        if self.sparse_ranges.is_empty(){
+            return Ok(stored);
+        }
+
+        let mut out: Vec<u8> = vec![0; self.channel_count as usize];
+        let mut offset = 0;
+        for (start, count) in &self.sparse_ranges{
+            if offset >= stored.len(){
+                break;
+            }
+            let (start, count) = (*start as usize, *count as usize);
+            let end = (offset + count).min(stored.len());
+            out[start..start + (end - offset)].copy_from_slice(&stored[offset..end]);
+            offset += count;
+        }
+        return Ok(out);
+    }
+}
+
+// Writes uncompressed fseq v2 files, frames must all be `channel_count` long
+pub struct FseqWriter{
+    channel_count: u32,
+    step_ms: u8,
+    frames: Vec<Vec<u8>>,
+    variable_headers: Vec<([u8; 2], String)>
+}
+
+impl FseqWriter{
+    pub fn new(channel_count: u32, step_ms: u8) -> FseqWriter{
+        return FseqWriter { channel_count, step_ms: step_ms.max(1), frames: Vec::new(), variable_headers: Vec::new() };
+    }
+    pub fn add_variable_header(&mut self, code: [u8; 2], value: String){
+        self.variable_headers.push((code, value));
+    }
+    pub fn add_frame(&mut self, mut frame: Vec<u8>){
+        frame.resize(self.channel_count as usize, 0);
+        self.frames.push(frame);
+    }
+    pub fn frame_count(&self) -> usize{
+        return self.frames.len();
+    }
+
+    pub fn write_to(&self, writer: &mut dyn Write) -> io::Result<()>{
+        let mut headers: Vec<u8> = Vec::new();
+        for (code, value) in &self.variable_headers{
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.push(0);
+            let length = (bytes.len() + 4) as u16;
+            headers.extend_from_slice(&length.to_le_bytes());
+            headers.extend_from_slice(code);
+            headers.extend_from_slice(&bytes);
+        }
+        let variable_offset: usize = 32;
+        // Channel data is conventionally aligned to four bytes
+        let data_offset = (variable_offset + headers.len()).div_ceil(4) * 4;
+        if data_offset > u16::MAX as usize{
+            return Err(invalid("fseq headers are too large"));
+        }
+
+        let mut out: Vec<u8> = Vec::with_capacity(data_offset);
+        out.extend_from_slice(b"PSEQ");
+        out.extend_from_slice(&(data_offset as u16).to_le_bytes());
+        out.push(0);
+        out.push(2);
+        out.extend_from_slice(&(variable_offset as u16).to_le_bytes());
+        out.extend_from_slice(&self.channel_count.to_le_bytes());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        out.push(self.step_ms);
+        out.push(0);
+        out.push(0);
+        out.push(0);
+        out.push(0);
+        out.push(0);
+        out.extend_from_slice(&rand::random::<u64>().to_le_bytes());
+        out.extend_from_slice(&headers);
+        out.resize(data_offset, 0);
+
+        writer.write_all(&out)?;
+        for i in &self.frames{
+            writer.write_all(i)?;
+        }
+        return writer.flush();
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()>{
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        return self.write_to(&mut file);
+    }
+}
+
+const CLOCK_WRAP: u128 = 1 << 31;
+// Updates further apart than this many frames mean the instance was off, so
+// playback resumes where it stopped instead of jumping ahead
+const MAX_GAP_FRAMES: i64 = 4;
+
+// Plays an fseq file as a profile. The frame shown follows the wall clock at
+// the file's step time, however often the system updates, and picks up where
+// it stopped after the instance was off; "position_ms" holds how far the
+// instance has played and can be set to seek. Each light reads
+// three channels starting at the instance's "channel:<uid>" value, then the
+// strip or matrix's own start channel, or is packed in id order from channel 0.
+pub struct FseqPlayer{
+    path: String,
+    file: FseqFile,
+    clock: Instant
+}
+
+impl FseqPlayer{
+    pub fn new(path: &str) -> io::Result<FseqPlayer>{
+        return Ok(FseqPlayer { path: path.to_string(), file: FseqFile::open(path)?, clock: Instant::now() });
+    }
+    pub fn get_file(&self) -> &FseqFile{
+        return &self.file;
+    }
+
+    pub fn map_channels(instance: &mut Profile, uid: &str, start_channel: u32){
+        instance.set_data(&format!("channel:{}", uid), ProfileData::Int(start_channel as i32));
+    }
+
+    // Milliseconds since the player was loaded, wrapped to fit profile data.
+    // Only differences between two readings are used, and those unwrap.
+    fn now_ms(&self) -> i32{
+        return (self.clock.elapsed().as_millis() % CLOCK_WRAP) as i32;
+    }
+}
+
+impl ProfileInterface for FseqPlayer{
+    fn profile_name(&self) -> String{
+        return format!("FSEQ {}", self.path);
+    }
+
+    fn created(&self, parent: &mut Profile){
+        parent.set_data("frame", ProfileData::Int(0));
+        parent.set_data("position_ms", ProfileData::Int(0));
+        parent.set_data("clock_ms", ProfileData::Int(self.now_ms()));
+        parent.set_data("loop", ProfileData::Bool(true));
+    }
+
+    fn update(&self, parent: &mut Profile){
+        let now = self.now_ms();
+        let last = parent.get_int("clock_ms").unwrap_or(now);
+        parent.set_data("clock_ms", ProfileData::Int(now));
+        let step = self.file.get_step_ms().max(1) as i32;
+        let mut elapsed = (now as i64 - last as i64).rem_euclid(CLOCK_WRAP as i64);
+        if elapsed > MAX_GAP_FRAMES * step as i64{
+            elapsed = 0;
+        }
+        let mut position = parent.get_int("position_ms").unwrap_or(0).max(0).saturating_add(elapsed as i32);
+
+        let count = self.file.get_frame_count() as i32;
+        if position / step >= count{
+            let looping = match parent.get_data("loop"){
+                Some(ProfileData::Bool(x)) => *x,
+                _ => false
+            };
+            if !looping || count == 0{
+                parent.set_data("position_ms", ProfileData::Int(position));
+                return;
+            }
+            position %= count.saturating_mul(step);
+        }
+        parent.set_data("position_ms", ProfileData::Int(position));
+        let frame = (position / step) as u32;
+        let channels = match self.file.get_frame(frame){
+            Ok(x) => x,
+            Err(_) => {
+                parent.set_error(true);
+                return;
+            }
+        };
+
+        let mut ids = parent.m().get_all_ids();
+        ids.sort();
+        let mut next_channel: usize = 0;
+        for id in ids{
+            let strip_channel = parent.m().get_light(id).and_then(|x| x.get_start_channel());
+            let mapped = match parent.m().get_uid(id){
+                Some(uid) => parent.get_int(&format!("channel:{}", uid)),
+                None => None
+            };
+            let start = match (mapped, strip_channel){
+                (Some(x), _) => x.max(0) as usize,
+                (None, Some(x)) => x as usize,
+                (None, None) => next_channel
+            };
+            let light = match parent.m().get_light_mut(id){
+                None => continue,
+                Some(x) => x
+            };
+            let size = light.size();
+            for (index, i) in light._get_lights_mut().into_iter().enumerate(){
+                let c = start + index * 3;
+                if c + 3 > channels.len(){
+                    break;
+                }
+                i.set_color(Color::new(channels[c], channels[c + 1], channels[c + 2]));
+                i.set_transp(0);
+            }
+            next_channel = next_channel.max(start + size * 3);
+        }
+
+        parent.set_data("frame", ProfileData::Int(frame as i32));
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::managers::light_manager::LightManager;
+
+    fn write(writer: &FseqWriter) -> Vec<u8>{
+        let mut out: Vec<u8> = Vec::new();
+        writer.write_to(&mut out).unwrap();
+        return out;
+    }
+
+    // A v2 header for uncompressed frames, followed by the given sparse ranges
+    fn sparse_header(stored_channels: u32, frame_count: u32, ranges: &[(u32, u32)]) -> Vec<u8>{
+        let data_offset = 32 + ranges.len() * 6;
+        let mut out: Vec<u8> = Vec::new();
+        out.extend_from_slice(b"PSEQ");
+        out.extend_from_slice(&(data_offset as u16).to_le_bytes());
+        out.push(0);
+        out.push(2);
+        out.extend_from_slice(&(data_offset as u16).to_le_bytes());
+        out.extend_from_slice(&stored_channels.to_le_bytes());
+        out.extend_from_slice(&frame_count.to_le_bytes());
+        out.push(25);
+        out.push(0);
+        out.extend_from_slice(&[0, 0, ranges.len() as u8, 0]);
+        out.extend_from_slice(&[0; 8]);
+        for (start, count) in ranges{
+            out.extend_from_slice(&start.to_le_bytes()[0..3]);
+            out.extend_from_slice(&count.to_le_bytes()[0..3]);
+        }
+        return out;
+    }
+
+    #[test]
+    fn writer_round_trip(){
+        let mut writer = FseqWriter::new(6, 50);
+        writer.add_variable_header(*b"sp", "test".to_string());
+        writer.add_frame(vec![1, 2, 3, 4, 5, 6]);
+        writer.add_frame(vec![7, 8, 9]);
+        let file = FseqFile::parse(write(&writer)).unwrap();
+
+        assert_eq!(file.get_version(), (2, 0));
+        assert_eq!(file.get_channel_count(), 6);
+        assert_eq!(file.get_frame_count(), 2);
+        assert_eq!(file.get_step_ms(), 50);
+        assert_eq!(file.get_compression(), FseqCompression::None);
+        assert_eq!(file.get_frame(0).unwrap(), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(file.get_frame(1).unwrap(), vec![7, 8, 9, 0, 0, 0]);
+        assert!(file.get_frame(2).is_err());
+    }
+
+    #[test]
+    fn sparse_ranges_are_spread_out(){
+        let mut data = sparse_header(4, 1, &[(2, 1), (10, 3)]);
+        data.extend_from_slice(&[9, 1, 2, 3]);
+        let file = FseqFile::parse(data).unwrap();
+
+        assert_eq!(file.get_channel_count(), 13);
+        let frame = file.get_frame(0).unwrap();
+        assert_eq!(frame[2], 9);
+        assert_eq!(&frame[10..13], &[1, 2, 3]);
+        assert_eq!(frame.iter().map(|x| *x as u32).sum::<u32>(), 15);
+    }
+
+    #[test]
+    fn sparse_ranges_larger_than_stored_are_rejected(){
+        let mut data = sparse_header(3, 1, &[(0, 2), (5, 2), (9, 2)]);
+        data.extend_from_slice(&[1, 2, 3]);
+        let error = FseqFile::parse(data).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_other_files(){
+        assert!(FseqFile::parse(b"not a sequence file at all....".to_vec()).is_err());
+        assert!(FseqFile::parse(Vec::new()).is_err());
+    }
+
+    fn t_player() -> FseqPlayer{
+        let mut writer = FseqWriter::new(3, 50);
+        for i in 0..100{
+            writer.add_frame(vec![i, 0, 0]);
+        }
+        return FseqPlayer { path: "test".to_string(), file: FseqFile::parse(write(&writer)).unwrap(), clock: Instant::now() };
+    }
+
+    #[test]
+    fn playback_resumes_where_it_stopped(){
+        let player = t_player();
+        let mut instance = Profile::new("test".to_string(), true, true, LightManager::new());
+        player.created(&mut instance);
+        instance.set_data("position_ms", ProfileData::Int(500));
+
+        // Ten seconds without updates, as if switched off
+        instance.set_data("clock_ms", ProfileData::Int(player.now_ms() - 10_000));
+        player.update(&mut instance);
+        assert_eq!(instance.get_int("frame"), Some(10));
+        assert!(instance.get_int("position_ms").unwrap() < 550);
+    }
+
+    #[test]
+    fn the_clock_unwraps(){
+        let player = t_player();
+        let mut instance = Profile::new("test".to_string(), true, true, LightManager::new());
+        player.created(&mut instance);
+        // The player was just loaded, so 100ms ago is on the far side of the wrap
+        let before = (player.now_ms() as i64 - 100).rem_euclid(CLOCK_WRAP as i64);
+        assert!(before > i32::MAX as i64 / 2);
+        instance.set_data("clock_ms", ProfileData::Int(before as i32));
+        player.update(&mut instance);
+        let position = instance.get_int("position_ms").unwrap();
+        assert!((100..150).contains(&position), "{}", position);
+    }
+}
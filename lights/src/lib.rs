@@ -1,4 +1,8 @@
 pub mod utils;
+pub mod color_temp;
 pub mod structs;
 pub mod managers;
-pub mod lighting_system;
\ No newline at end of file
+pub mod lighting_system;
+pub mod output;
+pub mod fseq;
+pub mod xlights;
\ No newline at end of file
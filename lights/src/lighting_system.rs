@@ -2,19 +2,36 @@ extern crate std;
 use std::collections::HashMap;
 use std::path::Path;
 use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+use log::*;
 
 
-use crate::{managers::{profile_manager::*, light_manager::*}, structs::{light_types::LightingTypes, profile::*}};
+use crate::fseq::FseqPlayer;
+use crate::output::FrameSink;
+use crate::{managers::{profile_manager::*, light_manager::*, health_manager::*, latency_manager::*}, structs::{assignment::LightAssignment, color::Color, light_primitive::{CapabilityMapping, Light, LightVec}, light_types::LightingTypes, profile::*, segment::Segment}};
+
+#[derive(Debug, Clone)]
+pub enum SystemEvent{
+    HealthChanged(HealthEvent)
+}
 
 pub struct System{
     profiles_dir: String,
     profiles: HashMap<String, ProfileLoader>,
-    light_state: LightManager
+    light_state: LightManager,
+    health: HealthManager,
+    latency: LatencyManager,
+    outputs: Vec<Box<dyn FrameSink>>,
+    frame_time: Option<Duration>,
+    next_frame: Option<Instant>,
+    events: Vec<SystemEvent>
 }
 
 impl System{
     pub fn new(profiles_dir: String) -> System{
-        return System{profiles_dir, profiles: HashMap::new(), light_state: LightManager::new()}
+        return System{profiles_dir, profiles: HashMap::new(), light_state: LightManager::new(), health: HealthManager::new(), latency: LatencyManager::new(),
+            outputs: Vec::new(), frame_time: None, next_frame: None, events: Vec::new()}
     }
 
     pub fn init(&mut self){
@@ -49,6 +66,20 @@ impl System{
         self.profiles.insert(name, p);
         return Ok(());
     }
+    pub fn add_fseq(&mut self, name: String, path: String) -> Result<(), ()>{
+        if self.profiles.contains_key(&name){
+            return Err(());
+        }
+        let player = match FseqPlayer::new(&path){
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to load fseq file {}: {}", path, e);
+                return Err(());
+            }
+        };
+        self.profiles.insert(name.clone(), ProfileLoader::new_builtin(name, Box::new(player)));
+        return Ok(());
+    }
     pub fn remove_profile(&mut self, name: String) -> Result<(), ()>{
         return match self.profiles.remove(&name){
             Some(_) => Ok(()),
@@ -74,6 +105,22 @@ impl System{
             }
         }
     }
+    pub fn create_instance_with(&mut self, profile_name: String, instance_name: String, assignment: LightAssignment) -> Result<(), ()>{
+        match self.profiles.get_mut(&profile_name){
+            None => Err(()),
+            Some(x) => {
+                x.generate_instance_with(instance_name, &self.light_state, assignment)
+            }
+        }
+    }
+    pub fn set_instance_assignment(&mut self, profile_name: String, instance_name: String, assignment: LightAssignment) -> Result<(), ()>{
+        match self.profiles.get_mut(&profile_name){
+            None => Err(()),
+            Some(x) => {
+                x.set_instance_assignment(instance_name, assignment, &self.light_state)
+            }
+        }
+    }
     pub fn remove_instance(&mut self, profile_name: String, instance_name: String) -> Result<(), ()>{
         match self.profiles.get_mut(&profile_name){
             None => Err(()),
@@ -104,6 +151,15 @@ impl System{
             }
         };
     }
+    pub fn set_instance_data(&mut self, profile_name: String, instance_name: String, key: &str, value: ProfileData) -> Result<(), ()>{
+        return match self.get_instance_mut(profile_name, instance_name){
+            None => Err(()),
+            Some(x) => {
+                x.set_data(key, value);
+                Ok(())
+            }
+        };
+    }
     pub fn get_instances_key(&self) -> Vec<(String, String)>{
         let mut out = Vec::new();
         for profile in self.get_profile_names(){
@@ -122,12 +178,124 @@ impl System{
 
     pub fn add_light(&mut self, light: LightingTypes)->u32{
         let out = self.light_state.add_light(light);
+        self.health.track(out);
+        self.update_light_structure();
+        return out;
+    }
+    pub fn add_light_with_uid(&mut self, light: LightingTypes, uid: String) -> Result<u32, ()>{
+        let out = self.light_state.add_light_with_uid(light, uid)?;
+        self.health.track(out);
+        self.update_light_structure();
+        return Ok(out);
+    }
+    pub fn get_id_by_uid(&self, uid: &str) -> Option<u32>{
+        return self.light_state.get_id_by_uid(uid);
+    }
+    pub fn get_uid(&self, id: u32) -> Option<String>{
+        return self.light_state.get_uid(id);
+    }
+    pub fn set_uid(&mut self, id: u32, uid: String) -> Result<(), ()>{
+        let out = self.light_state.set_uid(id, uid);
+        self.update_light_structure();
+        return out;
+    }
+    pub fn rename_light(&mut self, id: u32, name: String) -> Result<(), ()>{
+        let out = self.light_state.rename(id, name);
+        self.update_light_structure();
+        return out;
+    }
+    // Instances convert their own pixels the same way when they next sync
+    pub fn set_light_type(&mut self, id: u32, type_: Light) -> Result<(), ()>{
+        let out = self.light_state.set_light_type(id, type_);
+        self.update_light_structure();
+        return out;
+    }
+    pub fn set_mapping(&mut self, id: u32, mapping: CapabilityMapping) -> Result<(), ()>{
+        let out = self.light_state.set_mapping(id, mapping);
         self.update_light_structure();
         return out;
     }
     pub fn remove_light(&mut self, id:u32){
         self.light_state.remove_light(id);
+        self.health.untrack(id);
+        self.latency.remove(id);
+        self.update_light_structure();
+    }
+    pub fn import_xlights(&mut self, path: String) -> Result<Vec<u32>, ()>{
+        let xml = match fs::read_to_string(&path){
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to read xLights models {}: {}", path, e);
+                return Err(());
+            }
+        };
+        let models = match crate::xlights::parse_models(&xml){
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to parse xLights models {}: {}", path, e);
+                return Err(());
+            }
+        };
+        let mut out: Vec<u32> = Vec::with_capacity(models.len());
+        for i in models{
+            out.push(self.add_light(i.to_light()));
+        }
+        return Ok(out);
+    }
+    pub fn load_positions_csv(&mut self, path: String) -> Result<usize, ()>{
+        let csv = match fs::read_to_string(&path){
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to read positions {}: {}", path, e);
+                return Err(());
+            }
+        };
+        let out = match self.light_state.set_positions_csv(&csv){
+            Ok(x) => x,
+            Err(e) => {
+                error!("Failed to parse positions {}: {}", path, e);
+                return Err(());
+            }
+        };
         self.update_light_structure();
+        return Ok(out);
+    }
+    pub fn add_segment(&mut self, segment: Segment){
+        self.light_state.add_segment(segment);
+        self.update_light_structure();
+    }
+    pub fn remove_segment(&mut self, name: &str){
+        self.light_state.remove_segment(name);
+        self.update_light_structure();
+    }
+    pub fn add_tag(&mut self, id: u32, tag: String){
+        self.light_state.add_tag(id, tag);
+        self.update_light_structure();
+    }
+    pub fn remove_tag(&mut self, id: u32, tag: &str){
+        self.light_state.remove_tag(id, tag);
+        self.update_light_structure();
+    }
+    pub fn get_ids_with_tag(&self, tag: &str) -> Vec<u32>{
+        return self.light_state.get_ids_with_tag(tag);
+    }
+    pub fn set_room(&mut self, id: u32, room: Option<String>){
+        self.light_state.set_room(id, room);
+        self.update_light_structure();
+    }
+    pub fn get_room_ids(&self, room: &str) -> Vec<u32>{
+        return self.light_state.get_room_ids(room);
+    }
+    pub fn set_parent(&mut self, id: u32, parent: Option<u32>) -> Result<(), ()>{
+        let out = self.light_state.set_parent(id, parent);
+        self.update_light_structure();
+        return out;
+    }
+    pub fn get_children(&self, id: u32) -> Vec<u32>{
+        return self.light_state.get_children(id);
+    }
+    pub fn select(&self, selector: &str) -> Result<Vec<(u32, usize)>, String>{
+        return self.light_state.select(selector);
     }
     pub fn get_light(&self, id:u32) -> Option<&LightingTypes>{
         return self.light_state.get_light(id);
@@ -140,10 +308,185 @@ impl System{
     }
 
 
+    pub fn health(&self) -> &HealthManager{
+        return &self.health;
+    }
+    // Results are reported through report_send_success/failure so every change becomes an event
+    pub fn set_health_backoff(&mut self, base: Duration, max: Duration){
+        self.health.set_backoff(base, max);
+    }
+    pub fn set_health_offline_after(&mut self, failures: u32){
+        self.health.set_offline_after(failures);
+    }
+    pub fn get_health(&self, id: u32) -> Option<&DeviceHealth>{
+        return self.health.get(id);
+    }
+    pub fn can_send(&self, id: u32) -> bool{
+        return self.health.can_send(id);
+    }
+    pub fn report_send_success(&mut self, id: u32){
+        if let Some(e) = self.health.report_success(id){
+            self.events.push(SystemEvent::HealthChanged(e));
+        }
+    }
+    pub fn report_send_failure(&mut self, id: u32, error: String){
+        if let Some(e) = self.health.report_failure(id, error){
+            self.events.push(SystemEvent::HealthChanged(e));
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<SystemEvent>{
+        return self.events.drain(..).collect();
+    }
+
+
+    pub fn get_light_state(&self) -> &LightManager{
+        return &self.light_state;
+    }
+    pub fn get_frame(&self) -> u64{
+        return self.light_state.get_frame();
+    }
+    pub fn set_keepalive(&mut self, frames: Option<u64>){
+        self.light_state.set_keepalive(frames);
+    }
+
+
+    pub fn add_output(&mut self, output: Box<dyn FrameSink>){
+        self.outputs.push(output);
+    }
+    pub fn output_count(&self) -> usize{
+        return self.outputs.len();
+    }
+    pub fn clear_outputs(&mut self){
+        for mut i in self.outputs.drain(..){
+            if let Err(e) = i.finish(){
+                error!("Failed to finish output: {}", e);
+            }
+        }
+    }
+
+    pub fn set_frame_rate(&mut self, fps: Option<f64>){
+        self.frame_time = match fps{
+            Some(x) if x > 0.0 => Some(Duration::from_secs_f64(1.0 / x)),
+            _ => None
+        };
+        self.next_frame = None;
+    }
+
+    // Sleeps until the next frame is due when a frame rate is set
+    fn wait_for_frame(&mut self){
+        let frame_time = match self.frame_time{
+            None => return,
+            Some(x) => x
+        };
+        let now = Instant::now();
+        let next = match self.next_frame{
+            None => now,
+            Some(x) => x
+        };
+        if next > now{
+            thread::sleep(next - now);
+        }
+        // Don't try to catch up on frames that were missed entirely
+        let mut following = next + frame_time;
+        if following < Instant::now(){
+            following = Instant::now() + frame_time;
+        }
+        self.next_frame = Some(following);
+    }
+
+    // Outputs are fed the latency adjusted frame, the live state when nothing
+    // is offset. Going back to the live state everything is resent once, as
+    // its change tracking never saw the delayed frames.
+    fn write_outputs(&mut self){
+        if self.outputs.is_empty(){
+            self.latency.reset_output();
+            return;
+        }
+        let state = match self.latency.is_active(){
+            true => self.latency.output_frame(&self.light_state, Instant::now()),
+            false => {
+                if self.latency.reset_output(){
+                    self.light_state.touch_all();
+                }
+                &self.light_state
+            }
+        };
+        self.outputs.retain_mut(|x| match x.write_frame(state){
+            Ok(_) => true,
+            Err(e) => {
+                error!("Removing output after write failure: {}", e);
+                false
+            }
+        });
+    }
+
     pub fn update(&mut self){
+        self.wait_for_frame();
         for (_, i) in &mut self.profiles{
             i.update();
         }
+        self.composite();
+        self.light_state.commit_frame();
+        self.latency.push_frame(Instant::now(), &self.light_state);
+        self.write_outputs();
+    }
+
+    pub fn set_latency_offset(&mut self, id: u32, offset_ms: i64){
+        self.latency.set_offset(id, offset_ms);
+    }
+    pub fn get_latency_offset(&self, id: u32) -> i64{
+        return self.latency.get_offset(id);
+    }
+    pub fn get_output_light(&self, id: u32) -> Option<&LightingTypes>{
+        if !self.latency.is_active(){
+            return self.light_state.get_light(id);
+        }
+        return self.latency.get_output(id, Instant::now());
+    }
+    pub fn start_calibration(&mut self, period: Duration){
+        self.latency.start_calibration(Instant::now(), period);
+    }
+    pub fn stop_calibration(&mut self){
+        self.latency.stop_calibration();
+    }
+
+    // Instances are stacked by profile name, then instance name, the last one
+    // on top. Each starts from cleared lights (black, fully transparent).
+    // Instances that never give a light a transparency cover everything they
+    // were assigned, as before layering existed. Once an instance is layered
+    // (see Profile::set_layered) each light is mixed over the ones below by its
+    // transparency, 0 opaque to 255 invisible, so lights it leaves cleared show
+    // what is underneath.
+    fn composite(&mut self){
+        self.light_state.clear();
+
+        if let Some(on) = self.latency.calibration_state(Instant::now()){
+            let color = if on {Color::new(255, 255, 255)} else {Color::new(0, 0, 0)};
+            for id in self.light_state.get_all_ids(){
+                if let Some(x) = self.light_state.get_light_mut(id){
+                    x.set_color(color).set_temp(if on {6500} else {0}).set_transp(0);
+                }
+            }
+            return;
+        }
+
+        let mut profile_names = self.get_profile_names();
+        profile_names.sort();
+        for profile in profile_names{
+            let p = match self.profiles.get(&profile){
+                None => continue,
+                Some(x) => x
+            };
+            let mut instance_names = p.get_instance_names();
+            instance_names.sort();
+            for name in instance_names{
+                match p.get_instance(name){
+                    Some(x) if x.is_on() => self.light_state.composite(x.get_lights(), x.is_layered()),
+                    _ => ()
+                }
+            }
+        }
     }
 
     //pub fn update_light_state()
@@ -153,4 +496,34 @@ impl System{
             i.update_light_structure(&self.light_state);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::structs::light_types::Bulb;
+
+    #[test]
+    fn send_results_become_health_events(){
+        let mut system = System::new("profiles".to_string());
+        let id = system.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "porch".to_string())));
+        system.set_health_offline_after(2);
+
+        system.report_send_failure(id, "timeout".to_string());
+        system.report_send_failure(id, "timeout".to_string());
+        system.report_send_failure(id, "timeout".to_string());
+        system.report_send_success(id);
+        system.report_send_success(id);
+        let events: Vec<(HealthState, HealthState)> = system.take_events().into_iter()
+            .map(|e| match e{
+                SystemEvent::HealthChanged(x) => (x.old, x.new)
+            })
+            .collect();
+        assert_eq!(events, vec![
+            (HealthState::Online, HealthState::Degraded),
+            (HealthState::Degraded, HealthState::Offline),
+            (HealthState::Offline, HealthState::Online)
+        ]);
+        assert!(system.take_events().is_empty());
+    }
+}
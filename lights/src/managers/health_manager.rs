@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HealthState{
+    Online,
+    Degraded,
+    Offline
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthEvent{
+    pub id: u32,
+    pub old: HealthState,
+    pub new: HealthState,
+    pub error: Option<String>
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceHealth{
+    state: HealthState,
+    last_error: Option<String>,
+    last_success: Option<Instant>,
+    failures: u32,
+    next_retry: Option<Instant>
+}
+
+impl Default for DeviceHealth{
+    fn default() -> DeviceHealth{
+        return DeviceHealth::new();
+    }
+}
+
+impl DeviceHealth{
+    pub fn new() -> DeviceHealth{
+        return DeviceHealth { state: HealthState::Online, last_error: None, last_success: None, failures: 0, next_retry: None };
+    }
+    pub fn get_state(&self) -> HealthState{
+        return self.state;
+    }
+    pub fn get_last_error(&self) -> Option<String>{
+        return self.last_error.clone();
+    }
+    pub fn get_last_success(&self) -> Option<Instant>{
+        return self.last_success;
+    }
+    pub fn get_failures(&self) -> u32{
+        return self.failures;
+    }
+    pub fn get_next_retry(&self) -> Option<Instant>{
+        return self.next_retry;
+    }
+}
+
+pub struct HealthManager{
+    devices: HashMap<u32, DeviceHealth>,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    offline_after: u32
+}
+
+impl Default for HealthManager{
+    fn default() -> HealthManager{
+        return HealthManager::new();
+    }
+}
+
+impl HealthManager{
+    pub fn new() -> HealthManager{
+        return HealthManager {
+            devices: HashMap::new(),
+            base_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            offline_after: 5
+        };
+    }
+
+    pub fn set_backoff(&mut self, base: Duration, max: Duration){
+        self.base_backoff = base;
+        self.max_backoff = max;
+    }
+    pub fn set_offline_after(&mut self, failures: u32){
+        self.offline_after = failures.max(1);
+    }
+
+    pub fn track(&mut self, id: u32){
+        self.devices.entry(id).or_default();
+    }
+    pub fn untrack(&mut self, id: u32){
+        self.devices.remove(&id);
+    }
+    pub fn get(&self, id: u32) -> Option<&DeviceHealth>{
+        return self.devices.get(&id);
+    }
+    pub fn get_ids_with_state(&self, state: HealthState) -> Vec<u32>{
+        let mut out: Vec<u32> = Vec::new();
+        for (id, i) in &self.devices{
+            if i.state == state{
+                out.push(id.clone());
+            }
+        }
+        return out;
+    }
+
+    pub fn can_send(&self, id: u32) -> bool{
+        return self.can_send_at(id, Instant::now());
+    }
+    pub fn can_send_at(&self, id: u32, now: Instant) -> bool{
+        return match self.devices.get(&id){
+            None => false,
+            Some(x) => match x.next_retry{
+                None => true,
+                Some(t) => now >= t
+            }
+        };
+    }
+
+    pub fn report_success(&mut self, id: u32) -> Option<HealthEvent>{
+        return self.report_success_at(id, Instant::now());
+    }
+    pub fn report_success_at(&mut self, id: u32, now: Instant) -> Option<HealthEvent>{
+        let device = self.devices.get_mut(&id)?;
+        let old = device.state;
+
+        device.state = HealthState::Online;
+        device.last_success = Some(now);
+        device.failures = 0;
+        device.next_retry = None;
+
+        if old == HealthState::Online{
+            return None;
+        }
+        return Some(HealthEvent { id, old, new: HealthState::Online, error: None });
+    }
+
+    pub fn report_failure(&mut self, id: u32, error: String) -> Option<HealthEvent>{
+        return self.report_failure_at(id, error, Instant::now());
+    }
+    pub fn report_failure_at(&mut self, id: u32, error: String, now: Instant) -> Option<HealthEvent>{
+        let base = self.base_backoff;
+        let max = self.max_backoff;
+        let offline_after = self.offline_after;
+
+        let device = self.devices.get_mut(&id)?;
+        let old = device.state;
+
+        device.failures = device.failures.saturating_add(1);
+        device.last_error = Some(error.clone());
+
+        // Exponential backoff, doubling per consecutive failure up to the max.
+        // Past 31 doublings the factor no longer fits, which is well beyond any max.
+        let factor = 1u32.checked_shl(device.failures - 1).unwrap_or(u32::MAX);
+        let backoff = base.saturating_mul(factor).min(max);
+        device.next_retry = Some(now + backoff);
+
+        device.state = if device.failures >= offline_after{
+            HealthState::Offline
+        }else{
+            HealthState::Degraded
+        };
+
+        if old == device.state{
+            return None;
+        }
+        return Some(HealthEvent { id, old, new: device.state, error: Some(error) });
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn t_manager() -> (HealthManager, Instant){
+        let mut health = HealthManager::new();
+        health.set_backoff(Duration::from_millis(100), Duration::from_secs(1));
+        health.set_offline_after(3);
+        health.track(1);
+        return (health, Instant::now());
+    }
+
+    #[test]
+    fn failures_degrade_then_take_devices_offline(){
+        let (mut health, now) = t_manager();
+        assert!(health.report_failure_at(7, "untracked".to_string(), now).is_none());
+        assert!(health.report_success_at(1, now).is_none());
+
+        let e = health.report_failure_at(1, "timeout".to_string(), now).unwrap();
+        assert_eq!((e.old, e.new, e.error), (HealthState::Online, HealthState::Degraded, Some("timeout".to_string())));
+        assert!(health.report_failure_at(1, "timeout".to_string(), now).is_none());
+        let e = health.report_failure_at(1, "refused".to_string(), now).unwrap();
+        assert_eq!((e.old, e.new), (HealthState::Degraded, HealthState::Offline));
+        assert!(health.report_failure_at(1, "refused".to_string(), now).is_none());
+        assert_eq!(health.get_ids_with_state(HealthState::Offline), vec![1]);
+        assert_eq!(health.get(1).unwrap().get_last_error(), Some("refused".to_string()));
+
+        let e = health.report_success_at(1, now).unwrap();
+        assert_eq!((e.old, e.new, e.error), (HealthState::Offline, HealthState::Online, None));
+        let device = health.get(1).unwrap();
+        assert_eq!((device.get_failures(), device.get_next_retry(), device.get_last_success()), (0, None, Some(now)));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max(){
+        let (mut health, now) = t_manager();
+        assert!(health.can_send_at(1, now));
+        assert!(!health.can_send_at(2, now));
+        for expected in [100, 200, 400, 800, 1000, 1000]{
+            health.report_failure_at(1, "timeout".to_string(), now);
+            let retry = now + Duration::from_millis(expected);
+            assert_eq!(health.get(1).unwrap().get_next_retry(), Some(retry));
+            assert!(!health.can_send_at(1, retry - Duration::from_millis(1)));
+            assert!(health.can_send_at(1, retry));
+        }
+        health.report_success_at(1, now);
+        assert!(health.can_send_at(1, now));
+    }
+
+    #[test]
+    fn long_outages_do_not_overflow(){
+        let (mut health, now) = t_manager();
+        health.set_backoff(Duration::from_nanos(1), Duration::from_secs(3600));
+        for _ in 0..40{
+            health.report_failure_at(1, "timeout".to_string(), now);
+        }
+        assert_eq!(health.get(1).unwrap().get_next_retry(), Some(now + Duration::from_nanos(u32::MAX as u64)));
+        health.set_offline_after(0);
+        health.track(2);
+        let e = health.report_failure_at(2, "timeout".to_string(), now).unwrap();
+        assert_eq!(e.new, HealthState::Offline);
+    }
+}
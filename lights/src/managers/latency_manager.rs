@@ -0,0 +1,224 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::managers::light_manager::LightManager;
+use crate::structs::light_types::LightingTypes;
+
+pub struct LatencyManager{
+    offsets: HashMap<u32, i64>,
+    frames: VecDeque<(Instant, LightManager)>,
+    output: Option<LightManager>,
+    calibration: Option<(Instant, Duration)>
+}
+
+impl Default for LatencyManager{
+    fn default() -> LatencyManager{
+        return LatencyManager::new();
+    }
+}
+
+impl LatencyManager{
+    pub fn new() -> LatencyManager{
+        return LatencyManager { offsets: HashMap::new(), frames: VecDeque::new(), output: None, calibration: None };
+    }
+
+    // Offsets are in milliseconds, positive for devices that react late
+    pub fn set_offset(&mut self, id: u32, offset_ms: i64){
+        if offset_ms == 0{
+            self.offsets.remove(&id);
+        }else{
+            self.offsets.insert(id, offset_ms);
+        }
+    }
+    pub fn get_offset(&self, id: u32) -> i64{
+        return self.offsets.get(&id).cloned().unwrap_or(0);
+    }
+    pub fn remove(&mut self, id: u32){
+        self.offsets.remove(&id);
+    }
+
+    fn max_offset(&self) -> i64{
+        return self.offsets.values().cloned().fold(0, i64::max);
+    }
+    fn min_offset(&self) -> i64{
+        return self.offsets.values().cloned().fold(0, i64::min);
+    }
+
+    // How far behind the newest frame a device is fed, so that the slowest
+    // device is sent frames first and everything changes together
+    pub fn get_delay(&self, id: u32) -> Duration{
+        let delay = self.max_offset() - self.get_offset(id);
+        return Duration::from_millis(delay.max(0) as u64);
+    }
+
+    // Whether any device is offset, frames are only kept while one is
+    pub fn is_active(&self) -> bool{
+        return !self.offsets.is_empty();
+    }
+
+    pub fn push_frame(&mut self, now: Instant, frame: &LightManager){
+        if !self.is_active(){
+            self.frames.clear();
+            return;
+        }
+        self.frames.push_back((now, frame.clone()));
+
+        let span = Duration::from_millis((self.max_offset() - self.min_offset()) as u64);
+        while self.frames.len() > 1{
+            let second = self.frames[1].0;
+            if now.saturating_duration_since(second) >= span{
+                self.frames.pop_front();
+            }else{
+                break;
+            }
+        }
+    }
+
+    pub fn get_output(&self, id: u32, now: Instant) -> Option<&LightingTypes>{
+        let target = now.checked_sub(self.get_delay(id)).unwrap_or(now);
+
+        let mut out: Option<&LightManager> = None;
+        for (time, frame) in &self.frames{
+            if *time <= target || out.is_none(){
+                out = Some(frame);
+            }else{
+                break;
+            }
+        }
+        return match out{
+            None => None,
+            Some(x) => x.get_light(id)
+        };
+    }
+
+    // Swaps each light in frame for the delayed state it should be sent now
+    pub fn apply(&self, frame: &mut LightManager, now: Instant){
+        for id in frame.get_all_ids(){
+            if self.get_delay(id).is_zero(){
+                continue;
+            }
+            if let (Some(delayed), Some(x)) = (self.get_output(id, now), frame.get_light_mut(id)){
+                *x = delayed.clone();
+            }
+        }
+    }
+
+    // The frame outputs should be sent, with change tracking of its own so
+    // changes count from when they leave the delay rather than when drawn
+    pub fn output_frame(&mut self, live: &LightManager, now: Instant) -> &LightManager{
+        let mut frame = live.clone();
+        self.apply(&mut frame, now);
+        frame.recommit_frame(self.output.take());
+        return self.output.insert(frame);
+    }
+    // Drops the delayed output, true if there was one
+    pub fn reset_output(&mut self) -> bool{
+        return self.output.take().is_some();
+    }
+
+    pub fn start_calibration(&mut self, now: Instant, period: Duration){
+        self.calibration = Some((now, period));
+    }
+    pub fn stop_calibration(&mut self){
+        self.calibration = None;
+    }
+    pub fn is_calibrating(&self) -> bool{
+        return self.calibration.is_some();
+    }
+
+    // Whether the calibration flash is lit at the given time
+    pub fn calibration_state(&self, now: Instant) -> Option<bool>{
+        let (start, period) = self.calibration?;
+        let period = period.as_millis().max(2);
+        let elapsed = now.saturating_duration_since(start).as_millis();
+        return Some(elapsed % period < period / 2);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::structs::color::Color;
+    use crate::structs::light_primitive::*;
+    use crate::structs::light_types::Bulb;
+
+    fn t_frame(lights: &mut LightManager, color: Color) -> LightManager{
+        for id in lights.get_all_ids(){
+            lights.get_light_mut(id).unwrap().set_color(color);
+        }
+        return lights.clone();
+    }
+    fn t_color(lights: &LightManager, id: u32) -> Color{
+        return lights.get_light(id).unwrap()._get_lights()[0].get_color();
+    }
+
+    #[test]
+    fn frames_are_only_kept_while_offset(){
+        let mut lights = LightManager::new();
+        lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "a".to_string())));
+        let mut latency = LatencyManager::default();
+        latency.push_frame(Instant::now(), &lights);
+        assert!(!latency.is_active());
+        assert!(latency.frames.is_empty());
+    }
+
+    #[test]
+    fn early_devices_are_fed_older_frames(){
+        let mut lights = LightManager::new();
+        let slow = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "slow".to_string())));
+        let fast = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.2".to_string(), "fast".to_string())));
+        let mut latency = LatencyManager::new();
+        latency.set_offset(slow, 100);
+
+        let start = Instant::now();
+        let (red, blue) = (Color::new(255, 0, 0), Color::new(0, 0, 255));
+        latency.push_frame(start, &t_frame(&mut lights, red));
+        let now = start + Duration::from_millis(60);
+        let mut frame = t_frame(&mut lights, blue);
+        latency.push_frame(now, &frame);
+
+        latency.apply(&mut frame, now);
+        assert_eq!(t_color(&frame, slow), blue);
+        assert_eq!(t_color(&frame, fast), red);
+
+        let later = start + Duration::from_millis(160);
+        let mut frame = t_frame(&mut lights, blue);
+        latency.push_frame(later, &frame);
+        latency.apply(&mut frame, later);
+        assert_eq!(t_color(&frame, fast), blue);
+    }
+
+    #[test]
+    fn delayed_changes_are_tracked_when_sent(){
+        let mut lights = LightManager::new();
+        let slow = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "slow".to_string())));
+        let fast = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.2".to_string(), "fast".to_string())));
+        let mut latency = LatencyManager::new();
+        latency.set_offset(slow, 100);
+
+        let start = Instant::now();
+        let mut frames = Vec::new();
+        for (ms, color) in [(0, Color::new(255, 0, 0)), (60, Color::new(0, 0, 255)), (160, Color::new(0, 0, 255))]{
+            let now = start + Duration::from_millis(ms);
+            t_frame(&mut lights, color);
+            let frame = lights.commit_frame();
+            latency.push_frame(now, &lights);
+            let out = latency.output_frame(&lights, now);
+            assert_eq!(out.get_frame(), frame);
+            frames.push((frame, out.clone()));
+        }
+
+        // The fast bulb only turns blue once its delay has passed, although
+        // the live frame stopped changing before that
+        let (first, _) = &frames[0];
+        let (second, out) = &frames[1];
+        assert_eq!(out.get_changed_ids(*first), vec![slow]);
+        assert_eq!(lights.get_changed_ids(*second), Vec::<u32>::new());
+        let (_, out) = &frames[2];
+        assert_eq!(out.get_changed_ids(*second), vec![fast]);
+        assert_eq!(out.get_changed_indices(fast, *second), vec![0]);
+
+        assert!(latency.reset_output());
+        assert!(!latency.reset_output());
+    }
+}
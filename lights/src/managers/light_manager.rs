@@ -1,22 +1,185 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::structs::{light_types::*, light_primitive::*};
+use crate::structs::{light_types::*, light_primitive::*, position::*, segment::*, selector::Selector};
+use crate::utils::slugify;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StructureChange{
+    Name,
+    Pin,
+    Length{old: usize, new: usize},
+    LightType,
+    Layout,
+    Ip,
+    Members{added: Vec<String>, removed: Vec<String>},
+    Positions,
+    StartChannel,
+    // The entry became a different kind of light and was replaced
+    Replaced
+}
+
+// What sync_structure changed, handed to instances through structure_changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StructureDiff{
+    pub added: Vec<u32>,
+    pub removed: Vec<u32>,
+    pub changed: Vec<(u32, Vec<StructureChange>)>,
+    pub metadata: bool
+}
+
+impl StructureDiff{
+    pub fn is_empty(&self) -> bool{
+        return self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && !self.metadata;
+    }
+    pub fn get_changes(&self, id: u32) -> Option<&Vec<StructureChange>>{
+        return self.changed.iter().find(|x| x.0 == id).map(|x| &x.1);
+    }
+}
+
+fn same_kind(a: &Light, b: &Light) -> bool{
+    return std::mem::discriminant(a) == std::mem::discriminant(b);
+}
+
+fn structure_changes(this: &LightingTypes, base: &LightingTypes) -> Vec<StructureChange>{
+    let mut out: Vec<StructureChange> = Vec::new();
+    if this.get_name() != base.get_name(){
+        out.push(StructureChange::Name);
+    }
+    match (this, base){
+        (LightingTypes::LightStrip(x), LightingTypes::LightStrip(y)) => {
+            if x.get_pin() != y.get_pin(){
+                out.push(StructureChange::Pin);
+            }
+            if x.get_length() != y.get_length(){
+                out.push(StructureChange::Length { old: x.get_length(), new: y.get_length() });
+            }
+            if x.get_light_type() != y.get_light_type(){
+                out.push(StructureChange::LightType);
+            }
+            if x.get_start_channel() != y.get_start_channel(){
+                out.push(StructureChange::StartChannel);
+            }
+            if x.get_position_list() != y.get_position_list(){
+                out.push(StructureChange::Positions);
+            }
+        },
+        (LightingTypes::LightMatrix(x), LightingTypes::LightMatrix(y)) => {
+            if x.get_pin() != y.get_pin(){
+                out.push(StructureChange::Pin);
+            }
+            if x.get_light_type() != y.get_light_type(){
+                out.push(StructureChange::LightType);
+            }
+            if x.get_layout() != y.get_layout(){
+                out.push(StructureChange::Layout);
+                if x.size() != y.size(){
+                    out.push(StructureChange::Length { old: x.size(), new: y.size() });
+                }
+            }
+            if x.get_start_channel() != y.get_start_channel(){
+                out.push(StructureChange::StartChannel);
+            }
+        },
+        (LightingTypes::Bulb(x), LightingTypes::Bulb(y)) => {
+            if x.get_ip() != y.get_ip(){
+                out.push(StructureChange::Ip);
+            }
+            let (a, b) = (x._get_lights()[0], y._get_lights()[0]);
+            if !same_kind(a, b) || a.get_mapping() != b.get_mapping(){
+                out.push(StructureChange::LightType);
+            }
+            if x.get_positions() != y.get_positions(){
+                out.push(StructureChange::Positions);
+            }
+        },
+        (LightingTypes::BulbGroup(x), LightingTypes::BulbGroup(y)) => {
+            let (old, new) = (x.get_ips(), y.get_ips());
+            let added: Vec<String> = new.iter().filter(|i| !old.contains(i)).cloned().collect();
+            let removed: Vec<String> = old.iter().filter(|i| !new.contains(i)).cloned().collect();
+            if !added.is_empty() || !removed.is_empty(){
+                out.push(StructureChange::Members { added, removed });
+            }else if old != new{
+                out.push(StructureChange::Members { added: Vec::new(), removed: Vec::new() });
+            }
+            let retyped = (0..x.size()).map(|i| x.get_bulb(i)).any(|a| {
+                match (0..y.size()).map(|i| y.get_bulb(i)).find(|b| b.get_ip() == a.get_ip()){
+                    None => false,
+                    Some(b) => {
+                        let (a, b) = (a._get_lights()[0], b._get_lights()[0]);
+                        !same_kind(a, b) || a.get_mapping() != b.get_mapping()
+                    }
+                }
+            });
+            if retyped{
+                out.push(StructureChange::LightType);
+            }
+            if x.get_positions() != y.get_positions(){
+                out.push(StructureChange::Positions);
+            }
+        },
+        _ => return vec![StructureChange::Replaced]
+    }
+    return out;
+}
 
 #[derive(Debug, Clone)]
 pub struct LightManager{
     next_id: u32,
-    lights: HashMap<u32, LightingTypes>
+    lights: HashMap<u32, LightingTypes>,
+    // The u32 handles are only valid while running, anything kept across
+    // restarts or edits refers to lights by uid
+    uids: HashMap<u32, String>,
+    ids: HashMap<String, u32>,
+    segments: HashMap<String, Segment>,
+    tags: HashMap<String, HashSet<String>>,
+    rooms: HashMap<String, String>,
+    parents: HashMap<String, String>,
+    frame: u64,
+    snapshot: HashMap<u32, Vec<Light>>,
+    generations: HashMap<u32, Vec<u64>>,
+    last_sent: HashMap<u32, u64>,
+    keepalive: Option<u64>
 }
 
 impl LightManager{
     pub fn new() -> LightManager{
-        return LightManager { next_id: 0, lights: HashMap::new() }
+        return LightManager {
+            next_id: 0,
+            lights: HashMap::new(),
+            uids: HashMap::new(),
+            ids: HashMap::new(),
+            segments: HashMap::new(),
+            tags: HashMap::new(),
+            rooms: HashMap::new(),
+            parents: HashMap::new(),
+            frame: 0,
+            snapshot: HashMap::new(),
+            generations: HashMap::new(),
+            last_sent: HashMap::new(),
+            keepalive: None
+        }
     }
     pub fn new_template(&self) -> LightManager{
         let mut out = self.clone();
         out.clear();
         return out;
     }
+    // A copy holding only the given lights, and the segments fully inside them
+    pub fn filtered(&self, ids: &Vec<u32>) -> LightManager{
+        let mut out = self.clone();
+        out.lights.retain(|id, _| ids.contains(id));
+        out.uids.retain(|id, _| ids.contains(id));
+        out.ids.retain(|_, id| ids.contains(id));
+        let uids = &out.ids;
+        out.segments.retain(|_, s| s.get_uids().iter().all(|uid| uids.contains_key(uid)));
+        out.tags.retain(|uid, _| uids.contains_key(uid));
+        out.rooms.retain(|uid, _| uids.contains_key(uid));
+        out.parents.retain(|uid, _| uids.contains_key(uid));
+        out.snapshot.retain(|id, _| ids.contains(id));
+        out.generations.retain(|id, _| ids.contains(id));
+        out.last_sent.retain(|id, _| ids.contains(id));
+        return out;
+    }
     pub fn as_string(&self) -> String{
         let mut out = String::new();
         let lst = self.get_light_strip_ids();
@@ -26,6 +189,13 @@ impl LightManager{
                 out = (out + self.get_light(i).unwrap().as_string().as_str()) + "\n";
             }
         }
+        let lst = self.get_light_matrix_ids();
+        if lst.len() > 0{
+            out += "Light Matrices:\n";
+            for i in lst{
+                out = (out + self.get_light(i).unwrap().as_string().as_str()) + "\n";
+            }
+        }
         let lst = self.get_bulb_group_ids();
         if lst.len() > 0{
             out += "Bulb Groups:\n";
@@ -51,16 +221,60 @@ impl LightManager{
         return out;
     }
 
+    // The default uid is the name as a slug, numbered when it is taken, so a
+    // setup that adds the same lights in the same order gets the same uids
+    // on every run
     pub fn add_light(&mut self, light: LightingTypes)->u32{
+        let uid = self.unused_uid(&light.get_name());
         let next_id = self.get_id();
         self.add_light_manual(light, next_id);
+        self.uids.insert(next_id, uid.clone());
+        self.ids.insert(uid, next_id);
         return next_id;
     }
+    fn unused_uid(&self, name: &str) -> String{
+        let mut base = slugify(name);
+        if base.is_empty(){
+            base = "light".to_string();
+        }
+        let mut uid = base.clone();
+        let mut n = 2;
+        while self.ids.contains_key(&uid){
+            uid = format!("{}-{}", base, n);
+            n += 1;
+        }
+        return uid;
+    }
+    // Uids are slugs, lowercase words joined by single dashes
+    pub fn is_valid_uid(uid: &str) -> bool{
+        return !uid.is_empty() && slugify(uid) == uid;
+    }
+    // Adds a light under a uid chosen by the user, for example one saved from a previous run
+    pub fn add_light_with_uid(&mut self, light: LightingTypes, uid: String) -> Result<u32, ()>{
+        if !Self::is_valid_uid(&uid) || self.ids.contains_key(&uid){
+            return Err(());
+        }
+        let next_id = self.get_id();
+        self.add_light_manual(light, next_id);
+        self.uids.insert(next_id, uid.clone());
+        self.ids.insert(uid, next_id);
+        return Ok(next_id);
+    }
     fn add_light_manual(&mut self, light: LightingTypes, id: u32){
         self.lights.insert(id, light.clone());
     }
     pub fn remove_light(&mut self, id: u32){
         self.lights.remove(&id);
+        self.snapshot.remove(&id);
+        self.generations.remove(&id);
+        self.last_sent.remove(&id);
+        if let Some(uid) = self.uids.remove(&id){
+            self.ids.remove(&uid);
+            self.tags.remove(&uid);
+            self.rooms.remove(&uid);
+            self.parents.remove(&uid);
+            self.parents.retain(|_, parent| *parent != uid);
+        }
     }
     pub fn get_light(&self, id: u32)->Option<&LightingTypes>{
         return self.lights.get(&id);
@@ -69,6 +283,243 @@ impl LightManager{
         return self.lights.get_mut(&id);
     }
 
+    pub fn get_uid(&self, id: u32) -> Option<String>{
+        return self.uids.get(&id).cloned();
+    }
+    // Moves everything stored under the old uid over to the new one
+    pub fn set_uid(&mut self, id: u32, uid: String) -> Result<(), ()>{
+        if !self.lights.contains_key(&id) || !Self::is_valid_uid(&uid){
+            return Err(());
+        }
+        let old = match self.ids.get(&uid){
+            Some(x) if *x == id => return Ok(()),
+            Some(_) => return Err(()),
+            None => self.uids.insert(id, uid.clone())
+        };
+        self.ids.insert(uid.clone(), id);
+        let old = match old{
+            None => return Ok(()),
+            Some(x) => x
+        };
+        self.ids.remove(&old);
+        if let Some(x) = self.tags.remove(&old){
+            self.tags.insert(uid.clone(), x);
+        }
+        if let Some(x) = self.rooms.remove(&old){
+            self.rooms.insert(uid.clone(), x);
+        }
+        if let Some(x) = self.parents.remove(&old){
+            self.parents.insert(uid.clone(), x);
+        }
+        for parent in self.parents.values_mut(){
+            if *parent == old{
+                *parent = uid.clone();
+            }
+        }
+        for segment in self.segments.values_mut(){
+            segment.replace_uid(&old, &uid);
+        }
+        return Ok(());
+    }
+    pub fn get_id_by_uid(&self, uid: &str) -> Option<u32>{
+        return self.ids.get(uid).cloned();
+    }
+    pub fn get_light_by_uid(&self, uid: &str) -> Option<&LightingTypes>{
+        return self.get_light(self.get_id_by_uid(uid)?);
+    }
+    pub fn get_light_by_uid_mut(&mut self, uid: &str) -> Option<&mut LightingTypes>{
+        let id = self.get_id_by_uid(uid)?;
+        return self.get_light_mut(id);
+    }
+    pub fn get_ids_by_name(&self, name: &str) -> Vec<u32>{
+        let mut out: Vec<u32> = Vec::new();
+        for (id, i) in &self.lights{
+            if i.get_name() == name{
+                out.push(id.clone());
+            }
+        }
+        out.sort();
+        return out;
+    }
+    // Names are labels only, ids and uids stay the same
+    pub fn rename(&mut self, id: u32, name: String) -> Result<(), ()>{
+        return match self.get_light_mut(id){
+            None => Err(()),
+            Some(x) => {
+                x.set_name(name);
+                Ok(())
+            }
+        };
+    }
+
+    pub fn set_light_type(&mut self, id: u32, type_: Light) -> Result<(), ()>{
+        return match self.get_light_mut(id){
+            None => Err(()),
+            Some(x) => {
+                x.set_light_type(type_);
+                Ok(())
+            }
+        };
+    }
+
+    pub fn set_mapping(&mut self, id: u32, mapping: CapabilityMapping) -> Result<(), ()>{
+        return match self.get_light_mut(id){
+            None => Err(()),
+            Some(x) => {
+                x.set_mapping(mapping);
+                Ok(())
+            }
+        };
+    }
+
+    pub fn add_segment(&mut self, segment: Segment){
+        self.segments.insert(segment.get_name(), segment);
+    }
+    pub fn remove_segment(&mut self, name: &str){
+        self.segments.remove(name);
+    }
+    pub fn get_segment(&self, name: &str) -> Option<&Segment>{
+        return self.segments.get(name);
+    }
+    pub fn get_segment_names(&self) -> Vec<String>{
+        return self.segments.keys().cloned().collect();
+    }
+    pub fn get_segment_view(&mut self, name: &str) -> Option<SegmentView<'_>>{
+        let segment = self.segments.get_mut(name)?;
+        return Some(SegmentView::new(segment, &mut self.lights, &self.ids));
+    }
+
+    pub fn add_tag(&mut self, id: u32, tag: String){
+        if let Some(uid) = self.uids.get(&id){
+            self.tags.entry(uid.clone()).or_default().insert(tag);
+        }
+    }
+    pub fn remove_tag(&mut self, id: u32, tag: &str){
+        let uid = match self.uids.get(&id){
+            None => return,
+            Some(x) => x
+        };
+        if let Some(x) = self.tags.get_mut(uid){
+            x.remove(tag);
+            if x.is_empty(){
+                self.tags.remove(uid);
+            }
+        }
+    }
+    pub fn get_tags(&self, id: u32) -> Vec<String>{
+        let mut out: Vec<String> = match self.uids.get(&id).and_then(|x| self.tags.get(x)){
+            None => Vec::new(),
+            Some(x) => x.iter().cloned().collect()
+        };
+        out.sort();
+        return out;
+    }
+    pub fn get_ids_with_tag(&self, tag: &str) -> Vec<u32>{
+        let mut out: Vec<u32> = Vec::new();
+        for (uid, i) in &self.tags{
+            if i.contains(tag){
+                out.extend(self.get_id_by_uid(uid));
+            }
+        }
+        out.sort();
+        return out;
+    }
+    pub fn get_all_tags(&self) -> Vec<String>{
+        let mut out: Vec<String> = self.tags.values().flatten().cloned().collect();
+        out.sort();
+        out.dedup();
+        return out;
+    }
+
+    pub fn set_room(&mut self, id: u32, room: Option<String>){
+        let uid = match self.uids.get(&id){
+            None => return,
+            Some(x) => x.clone()
+        };
+        match room{
+            Some(x) => {self.rooms.insert(uid, x);},
+            None => {self.rooms.remove(&uid);}
+        }
+    }
+    pub fn get_room(&self, id: u32) -> Option<String>{
+        return self.rooms.get(self.uids.get(&id)?).cloned();
+    }
+    pub fn get_room_ids(&self, room: &str) -> Vec<u32>{
+        let mut out: Vec<u32> = Vec::new();
+        for (uid, i) in &self.rooms{
+            if i == room{
+                out.extend(self.get_id_by_uid(uid));
+            }
+        }
+        out.sort();
+        return out;
+    }
+    pub fn get_room_names(&self) -> Vec<String>{
+        let mut out: Vec<String> = self.rooms.values().cloned().collect();
+        out.sort();
+        out.dedup();
+        return out;
+    }
+
+    pub fn set_parent(&mut self, id: u32, parent: Option<u32>) -> Result<(), ()>{
+        let uid = match self.uids.get(&id){
+            None => return Err(()),
+            Some(x) => x.clone()
+        };
+        let parent = match parent{
+            None => {
+                self.parents.remove(&uid);
+                return Ok(());
+            },
+            Some(x) => x
+        };
+        let parent_uid = match self.uids.get(&parent){
+            None => return Err(()),
+            Some(x) => x.clone()
+        };
+        // Refuse anything that would make a light its own ancestor
+        let mut current = Some(parent);
+        while let Some(x) = current{
+            if x == id{
+                return Err(());
+            }
+            current = self.get_parent(x);
+        }
+        self.parents.insert(uid, parent_uid);
+        return Ok(());
+    }
+    pub fn get_parent(&self, id: u32) -> Option<u32>{
+        return self.get_id_by_uid(self.parents.get(self.uids.get(&id)?)?);
+    }
+    pub fn get_children(&self, id: u32) -> Vec<u32>{
+        let uid = match self.uids.get(&id){
+            None => return Vec::new(),
+            Some(x) => x
+        };
+        let mut out: Vec<u32> = Vec::new();
+        for (child, parent) in &self.parents{
+            if parent == uid{
+                out.extend(self.get_id_by_uid(child));
+            }
+        }
+        out.sort();
+        return out;
+    }
+    pub fn get_descendants(&self, id: u32) -> Vec<u32>{
+        let mut out: Vec<u32> = Vec::new();
+        let mut pending = self.get_children(id);
+        while let Some(x) = pending.pop(){
+            out.push(x);
+            pending.append(&mut self.get_children(x));
+        }
+        out.sort();
+        return out;
+    }
+
+    pub fn select(&self, selector: &str) -> Result<Vec<(u32, usize)>, String>{
+        return Ok(Selector::parse(selector)?.resolve(self));
+    }
+
     pub fn clear(&mut self){
         for (_, i) in &mut self.lights{
             i.clear();
@@ -89,6 +540,16 @@ impl LightManager{
         }
         return out;
     }
+    pub fn get_light_matrix_ids(&self) -> Vec<u32> {
+        let mut out:Vec<u32> = Vec::new();
+        for (id, i) in &self.lights{
+            match i{
+                LightingTypes::LightMatrix(_) => out.push(id.clone()),
+                _ => ()
+            }
+        }
+        return out;
+    }
     pub fn get_bulb_ids(&self) -> Vec<u32> {
         let mut out:Vec<u32> = Vec::new();
         for (id, i) in &self.lights{
@@ -121,22 +582,495 @@ impl LightManager{
         return out;
     }
 
-    pub fn sync_structure(&mut self, state: &LightManager){
-        let mut ids = self.get_all_bulb_ids();
+    // Every light that has a position, as (id, index, position) in id order
+    pub fn get_positioned_lights(&self) -> Vec<(u32, usize, Position)>{
+        let mut ids = self.get_all_ids();
+        ids.sort();
+        let mut out: Vec<(u32, usize, Position)> = Vec::new();
+        for id in ids{
+            let light = self.get_light(id).unwrap();
+            for (index, p) in light.get_positions().into_iter().enumerate(){
+                if let Some(x) = p{
+                    out.push((id, index, x));
+                }
+            }
+        }
+        return out;
+    }
+    pub fn get_bounding_box(&self) -> Option<(Position, Position)>{
+        let positions = self.get_positioned_lights().into_iter().map(|x| x.2).collect();
+        return bounding_box(&positions);
+    }
+    pub fn get_light_bounding_box(&self, id: u32) -> Option<(Position, Position)>{
+        let positions = self.get_light(id)?.get_positions().into_iter().flatten().collect();
+        return bounding_box(&positions);
+    }
+    pub fn set_position(&mut self, id: u32, index: usize, position: Position){
+        match self.get_light_mut(id){
+            Some(LightingTypes::LightStrip(x)) => x.set_position(index, position),
+            Some(LightingTypes::Bulb(x)) => x.set_position(Some(position)),
+            Some(LightingTypes::BulbGroup(x)) => if index < x.size(){
+                x.get_bulb_mut(index).set_position(Some(position));
+            },
+            // Matrix positions follow from their layout
+            Some(LightingTypes::LightMatrix(_)) | None => ()
+        }
+    }
+    pub fn set_positions_csv(&mut self, csv: &str) -> Result<usize, String>{
+        let rows = parse_positions_csv(csv)?;
+        for (id, index, position) in &rows{
+            self.set_position(*id, *index, *position);
+        }
+        return Ok(rows.len());
+    }
+
+    // Whether any light is less than fully transparent, cleared lights are not
+    pub fn has_transparency(&self) -> bool{
+        return self.lights.values().any(|x| x._get_lights().iter().any(|l| l.get_transp() != 255));
+    }
+
+    // Blends layer over these lights. Unless layered, its transparency is
+    // ignored and it covers every light it holds.
+    pub fn composite(&mut self, layer: &LightManager, layered: bool){
+        for (id, i) in &mut self.lights{
+            let above = match layer.get_light(*id){
+                None => continue,
+                Some(x) => x
+            };
+            for (light, other) in i._get_lights_mut().into_iter().zip(above._get_lights()){
+                if std::mem::discriminant(light) != std::mem::discriminant(other){
+                    continue;
+                }
+                if layered{
+                    light.blend(other);
+                }else{
+                    let mut opaque = other.clone();
+                    opaque.set_transp(0);
+                    light.blend(&opaque);
+                }
+            }
+        }
+    }
+
+    pub fn get_frame(&self) -> u64{
+        return self.frame;
+    }
+    pub fn set_keepalive(&mut self, frames: Option<u64>){
+        self.keepalive = frames;
+    }
+    pub fn get_keepalive(&self) -> Option<u64>{
+        return self.keepalive;
+    }
+
+    // Commits this frame against previous instead of the frame it was cloned
+    // from, for copies whose lights were swapped after the clone. Without a
+    // previous every light is treated as changed.
+    pub fn recommit_frame(&mut self, previous: Option<LightManager>) -> u64{
+        match previous{
+            Some(x) => {
+                self.frame = x.frame;
+                self.snapshot = x.snapshot;
+                self.generations = x.generations;
+                self.last_sent = x.last_sent;
+            },
+            None => {
+                self.frame = self.frame.saturating_sub(1);
+                self.snapshot.clear();
+            }
+        }
+        return self.commit_frame();
+    }
+    // Marks every light changed in the current frame
+    pub fn touch_all(&mut self){
+        let frame = self.frame;
+        for i in self.generations.values_mut(){
+            i.iter_mut().for_each(|g| *g = frame);
+        }
+    }
+
+    pub fn commit_frame(&mut self) -> u64{
+        self.frame += 1;
+        let frame = self.frame;
+
+        for (id, i) in &self.lights{
+            let lights = i._get_lights();
+            let previous = self.snapshot.entry(*id).or_default();
+            let generation = self.generations.entry(*id).or_default();
+            generation.resize(lights.len(), frame);
+
+            let mut changed = previous.len() != lights.len();
+            for (index, light) in lights.iter().enumerate(){
+                if previous.get(index) != Some(*light){
+                    generation[index] = frame;
+                    changed = true;
+                }
+            }
+
+            // Resend unchanged state once the keepalive interval has passed
+            let last = self.last_sent.entry(*id).or_insert(frame);
+            if changed{
+                *last = frame;
+            }else if let Some(k) = self.keepalive{
+                if frame - *last >= k{
+                    for g in generation.iter_mut(){
+                        *g = frame;
+                    }
+                    *last = frame;
+                }
+            }
+
+            *previous = lights.into_iter().cloned().collect();
+        }
+        return frame;
+    }
+
+    pub fn get_generation(&self, id: u32) -> Option<u64>{
+        return self.generations.get(&id).map(|x| x.iter().cloned().max().unwrap_or(0));
+    }
+    pub fn get_changed_ids(&self, since: u64) -> Vec<u32>{
+        let mut out: Vec<u32> = Vec::new();
+        for id in self.get_all_ids(){
+            match self.get_generation(id){
+                Some(x) if x > since => out.push(id),
+                None => out.push(id),
+                _ => ()
+            }
+        }
+        return out;
+    }
+    pub fn get_changed_indices(&self, id: u32, since: u64) -> Vec<usize>{
+        let size = match self.get_light(id){
+            None => return Vec::new(),
+            Some(x) => x.size()
+        };
+        return match self.generations.get(&id){
+            None => (0..size).collect(),
+            Some(x) => (0..size)
+                .filter(|i| x.get(*i).is_none_or(|g| *g > since))
+                .collect()
+        };
+    }
+
+    // Brings the structure in line with state, keeping drawn pixels wherever
+    // the light they belong to still exists in the same form
+    pub fn sync_structure(&mut self, state: &LightManager) -> StructureDiff{
+        let mut diff = StructureDiff::default();
+        if self.uids != state.uids{
+            self.uids = state.uids.clone();
+            self.ids = state.ids.clone();
+            diff.metadata = true;
+        }
+        if self.segments != state.segments{
+            self.segments = state.segments.clone();
+            diff.metadata = true;
+        }
+        if self.tags != state.tags{
+            self.tags = state.tags.clone();
+            diff.metadata = true;
+        }
+        if self.rooms != state.rooms{
+            self.rooms = state.rooms.clone();
+            diff.metadata = true;
+        }
+        if self.parents != state.parents{
+            self.parents = state.parents.clone();
+            diff.metadata = true;
+        }
+        let mut ids = self.get_all_ids();
         let mut other_ids = state.get_all_ids();
         ids.append(&mut other_ids);
+        ids.sort();
+        ids.dedup();
         for id in ids{
-            let this = self.get_light(id);
-            let base = state.get_light(id);
+            let base = match state.get_light(id){
+                None => {
+                    self.remove_light(id);
+                    diff.removed.push(id);
+                    continue;
+                },
+                Some(x) => x
+            };
+            let this = match self.lights.get_mut(&id){
+                None => {
+                    let mut light = base.clone();
+                    light.clear();
+                    self.add_light_manual(light, id);
+                    diff.added.push(id);
+                    continue;
+                },
+                Some(x) => x
+            };
 
-            if let None = this{
-                self.add_light_manual(base.unwrap().clone(), id);
-            }else if let None = base{
-                self.remove_light(id);
+            let changes = structure_changes(this, base);
+            if changes.is_empty(){
+                continue;
+            }
+            if changes.contains(&StructureChange::Replaced){
+                let mut light = base.clone();
+                light.clear();
+                *this = light;
             }else{
-                
+                this.sync_structure(base);
             }
+            diff.changed.push((id, changes));
+        }
+        return diff;
+    }
+
+}
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::structs::color::Color;
+
+    fn t_colors(lights: &LightManager, id: u32) -> Vec<Color>{
+        return lights.get_light(id).unwrap()._get_lights().iter().map(|x| x.get_color()).collect();
+    }
+
+    fn t_group(lights: &mut LightManager) -> u32{
+        let mut group = BulbGroup::new("group".to_string());
+        group.add_bulb(Bulb::new("10.0.0.1".to_string(), "a".to_string()));
+        group.add_bulb(Bulb::new("10.0.0.2".to_string(), "b".to_string()));
+        return lights.add_light(LightingTypes::BulbGroup(group));
+    }
+
+    #[test]
+    fn sync_structure_retypes_bulb_groups(){
+        let mut state = LightManager::new();
+        let id = t_group(&mut state);
+        let mut instance = state.new_template();
+
+        state.set_light_type(id, TLight::default_enum()).unwrap();
+        let diff = instance.sync_structure(&state);
+        assert_eq!(diff.get_changes(id), Some(&vec![StructureChange::LightType]));
+        for i in instance.get_light(id).unwrap()._get_lights(){
+            assert!(matches!(i, Light::T(_)));
+        }
+        assert!(instance.sync_structure(&state).is_empty());
+
+        state.set_mapping(id, CapabilityMapping::Ignore).unwrap();
+        let diff = instance.sync_structure(&state);
+        assert_eq!(diff.get_changes(id), Some(&vec![StructureChange::LightType]));
+        for i in instance.get_light(id).unwrap()._get_lights(){
+            assert_eq!(i.get_mapping(), CapabilityMapping::Ignore);
+        }
+    }
+
+    #[test]
+    fn sync_structure_reports_members_and_lengths(){
+        let mut state = LightManager::new();
+        let group = t_group(&mut state);
+        let strip = state.add_light(LightStrip::new_enum("strip".to_string(), 1, 3, RgbLight::default_enum()));
+        let mut instance = state.new_template();
+        instance.get_light_mut(strip).unwrap().set_color(Color::new(1, 2, 3));
+
+        if let Some(LightingTypes::BulbGroup(x)) = state.get_light_mut(group){
+            x.add_bulb(Bulb::new("10.0.0.3".to_string(), "c".to_string()));
+        }
+        if let Some(LightingTypes::LightStrip(x)) = state.get_light_mut(strip){
+            x.set_length(5);
+        }
+        let diff = instance.sync_structure(&state);
+        assert!(diff.get_changes(group).unwrap().contains(&StructureChange::Members { added: vec!["10.0.0.3".to_string()], removed: Vec::new() }));
+        assert_eq!(diff.get_changes(strip), Some(&vec![StructureChange::Length { old: 3, new: 5 }]));
+        assert_eq!(instance.get_light(group).unwrap().size(), 3);
+        // Pixels that still exist keep what the instance drew
+        assert_eq!(t_colors(&instance, strip)[..3], [Color::new(1, 2, 3); 3]);
+        assert_eq!(t_colors(&instance, strip).len(), 5);
+
+        state.remove_light(group);
+        let diff = instance.sync_structure(&state);
+        assert_eq!(diff.removed, vec![group]);
+        assert!(instance.get_light(group).is_none());
+    }
+
+    #[test]
+    fn layers_cover_or_blend_by_transparency(){
+        let mut state = LightManager::new();
+        let id = state.add_light(LightStrip::new_enum("strip".to_string(), 1, 3, RgbLight::default_enum()));
+        let (red, blue, black) = (Color::new(255, 0, 0), Color::new(0, 0, 255), Color::new(0, 0, 0));
+
+        let mut below = state.new_template();
+        below.get_light_mut(id).unwrap().set_color(red);
+        let mut above = state.new_template();
+        above.get_light_mut(id).unwrap().set_color_index(blue, 1);
+        assert!(!above.has_transparency());
+
+        state.clear();
+        state.composite(&below, false);
+        state.composite(&above, false);
+        assert_eq!(t_colors(&state, id), vec![black, blue, black]);
+
+        above.get_light_mut(id).unwrap().set_transp_index(0, 1);
+        assert!(above.has_transparency());
+        state.clear();
+        state.composite(&below, false);
+        state.composite(&above, true);
+        assert_eq!(t_colors(&state, id), vec![red, blue, red]);
+    }
+
+    fn t_tracked() -> (LightManager, u32, u32){
+        let mut lights = LightManager::new();
+        let strip = lights.add_light(LightStrip::new_enum("strip".to_string(), 1, 5, RgbLight::default_enum()));
+        let bulb = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "bulb".to_string())));
+        return (lights, strip, bulb);
+    }
+
+    fn t_changed(lights: &LightManager, since: u64) -> Vec<u32>{
+        let mut out = lights.get_changed_ids(since);
+        out.sort();
+        return out;
+    }
+
+    #[test]
+    fn commits_track_changed_lights_and_indices(){
+        let (mut lights, strip, bulb) = t_tracked();
+        // Nothing is committed yet, so everything is new
+        assert_eq!(t_changed(&lights, 0), vec![strip, bulb]);
+        assert_eq!(lights.get_changed_indices(strip, 0), vec![0, 1, 2, 3, 4]);
+
+        assert_eq!(lights.commit_frame(), 1);
+        assert_eq!(lights.get_generation(strip), Some(1));
+        assert_eq!(t_changed(&lights, 0), vec![strip, bulb]);
+        assert_eq!(lights.commit_frame(), 2);
+        assert!(t_changed(&lights, 1).is_empty());
+
+        for index in [2, 4]{
+            lights.get_light_mut(strip).unwrap()._get_lights_mut()[index].set_color(Color::new(255, 0, 0));
         }
+        assert_eq!(lights.commit_frame(), 3);
+        assert_eq!(t_changed(&lights, 2), vec![strip]);
+        assert_eq!(lights.get_changed_indices(strip, 2), vec![2, 4]);
+        assert!(lights.get_changed_indices(bulb, 2).is_empty());
+        assert!(lights.get_changed_indices(99, 0).is_empty());
+        // Older readers still see everything since their own frame
+        assert_eq!(lights.get_changed_indices(strip, 0), vec![0, 1, 2, 3, 4]);
+
+        // Setting the same color again is not a change
+        lights.get_light_mut(strip).unwrap()._get_lights_mut()[2].set_color(Color::new(255, 0, 0));
+        lights.commit_frame();
+        assert!(t_changed(&lights, 3).is_empty());
+
+        lights.touch_all();
+        assert_eq!(t_changed(&lights, 3), vec![strip, bulb]);
+    }
+
+    #[test]
+    fn keepalive_resends_unchanged_lights(){
+        let (mut lights, strip, bulb) = t_tracked();
+        lights.set_keepalive(Some(3));
+        lights.commit_frame();
+        lights.commit_frame();
+        lights.commit_frame();
+        assert!(t_changed(&lights, 1).is_empty());
+
+        // Three frames after the last send everything goes out again
+        assert_eq!(lights.commit_frame(), 4);
+        assert_eq!(t_changed(&lights, 3), vec![strip, bulb]);
+        assert_eq!(lights.get_changed_indices(strip, 3), vec![0, 1, 2, 3, 4]);
+
+        // A real change restarts the interval for that light only
+        lights.get_light_mut(bulb).unwrap()._get_lights_mut()[0].set_color(Color::new(0, 0, 255));
+        lights.commit_frame();
+        lights.commit_frame();
+        assert_eq!(lights.commit_frame(), 7);
+        assert_eq!(t_changed(&lights, 6), vec![strip]);
+        lights.commit_frame();
+        assert_eq!(t_changed(&lights, 7), vec![bulb]);
+
+        lights.set_keepalive(None);
+        for _ in 0..10{
+            lights.commit_frame();
+        }
+        assert!(t_changed(&lights, 8).is_empty());
+    }
+
+    #[test]
+    fn recommitting_without_a_previous_frame_resends_everything(){
+        let (mut lights, strip, bulb) = t_tracked();
+        lights.commit_frame();
+        let previous = lights.clone();
+        lights.commit_frame();
+        let mut copy = lights.clone();
+        assert_eq!(copy.recommit_frame(Some(previous)), 2);
+        assert!(t_changed(&copy, 1).is_empty());
+        assert_eq!(copy.recommit_frame(None), 2);
+        assert_eq!(t_changed(&copy, 1), vec![strip, bulb]);
     }
 
-}
\ No newline at end of file
+    fn t_bulb(name: &str) -> LightingTypes{
+        return LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), name.to_string()));
+    }
+
+    #[test]
+    fn default_uids_are_numbered_slugs(){
+        let mut lights = LightManager::new();
+        let a = lights.add_light(t_bulb("Porch Left"));
+        let b = lights.add_light(t_bulb("porch left"));
+        let c = lights.add_light(t_bulb("Porch-Left!"));
+        let d = lights.add_light(t_bulb("**"));
+        assert_eq!([a, b, c, d].map(|x| lights.get_uid(x).unwrap()), ["porch-left", "porch-left-2", "porch-left-3", "light"]);
+        assert_eq!(lights.get_id_by_uid("porch-left-2"), Some(b));
+
+        // A freed uid is handed out again, the others keep theirs
+        lights.remove_light(a);
+        assert_eq!(lights.get_id_by_uid("porch-left"), None);
+        let e = lights.add_light(t_bulb("Porch Left"));
+        assert_eq!(lights.get_uid(e).unwrap(), "porch-left");
+        assert_eq!(lights.get_id_by_uid("porch-left-3"), Some(c));
+    }
+
+    #[test]
+    fn uids_must_be_unused_slugs(){
+        let mut lights = LightManager::new();
+        let a = lights.add_light(t_bulb("a"));
+        for uid in ["", "Porch", "porch left", "-porch", "porch-", "porch--left", "a"]{
+            assert!(lights.add_light_with_uid(t_bulb("b"), uid.to_string()).is_err(), "{} accepted", uid);
+        }
+        let b = lights.add_light_with_uid(t_bulb("b"), "porch-2".to_string()).unwrap();
+        assert_eq!(lights.get_all_ids().len(), 2);
+
+        assert!(lights.set_uid(b, "a".to_string()).is_err());
+        assert!(lights.set_uid(b, "Porch".to_string()).is_err());
+        assert!(lights.set_uid(99, "free".to_string()).is_err());
+        assert!(lights.set_uid(b, "porch-2".to_string()).is_ok());
+        assert_eq!(lights.get_uid(a).unwrap(), "a");
+        assert_eq!(lights.get_uid(b).unwrap(), "porch-2");
+    }
+
+    #[test]
+    fn renames_keep_references(){
+        let mut lights = LightManager::new();
+        let strip = lights.add_light(LightStrip::new_enum("strip".to_string(), 1, 4, RgbLight::default_enum()));
+        let bulb = lights.add_light(t_bulb("bulb"));
+        lights.add_tag(strip, "accent".to_string());
+        lights.set_room(strip, Some("hall".to_string()));
+        lights.set_parent(bulb, Some(strip)).unwrap();
+        let mut segment = Segment::new("s".to_string());
+        segment.add_range("strip".to_string(), 0, 2, false);
+        lights.add_segment(segment);
+
+        lights.rename(strip, "Front Strip".to_string()).unwrap();
+        assert_eq!(lights.get_uid(strip).unwrap(), "strip");
+        lights.set_uid(strip, "front".to_string()).unwrap();
+        assert_eq!(lights.get_id_by_uid("front"), Some(strip));
+        assert_eq!(lights.get_id_by_uid("strip"), None);
+        assert_eq!(lights.get_ids_with_tag("accent"), vec![strip]);
+        assert_eq!(lights.get_room_ids("hall"), vec![strip]);
+        assert_eq!(lights.get_parent(bulb), Some(strip));
+        assert_eq!(lights.get_children(strip), vec![bulb]);
+        assert_eq!(lights.get_segment("s").unwrap().get_uids(), vec!["front".to_string()]);
+        assert_eq!(lights.get_segment_view("s").unwrap().size(), 2);
+
+        // A new light taking the old uid does not inherit anything
+        let other = lights.add_light(t_bulb("strip"));
+        assert_eq!(lights.get_uid(other).unwrap(), "strip");
+        assert!(lights.get_tags(other).is_empty());
+        assert_eq!(lights.get_room(other), None);
+
+        lights.remove_light(strip);
+        assert_eq!(lights.get_parent(bulb), None);
+        assert!(lights.get_ids_with_tag("accent").is_empty());
+        assert_eq!(lights.get_segment_view("s").unwrap().size(), 0);
+    }
+}
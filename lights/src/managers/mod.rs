@@ -1,2 +1,4 @@
 pub mod profile_manager;
-pub mod light_manager;
\ No newline at end of file
+pub mod light_manager;
+pub mod health_manager;
+pub mod latency_manager;
@@ -14,6 +14,7 @@ use libloading::os::unix::*;
 
 
 use crate::managers::light_manager::LightManager;
+use crate::structs::assignment::LightAssignment;
 use crate::structs::profile::*;
 
 
@@ -39,6 +40,9 @@ impl ProfileLoader{
     pub fn new(dir: String, name: String) -> ProfileLoader{
         return ProfileLoader{dir: dir, name: name, library: Vec::new(), instances: HashMap::new(), interface: None, state: ProfileLoaderState::Unloaded};
     }
+    pub fn new_builtin(name: String, interface: Box<dyn ProfileInterface>) -> ProfileLoader{
+        return ProfileLoader{dir: String::new(), name: name, library: Vec::new(), instances: HashMap::new(), interface: Some(interface), state: ProfileLoaderState::Loaded};
+    }
 
     pub fn try_load(&mut self){
         _ = self.new_profile();
@@ -227,6 +231,10 @@ impl ProfileLoader{
     }
 
     pub fn generate_instance(&mut self, name: String, light_manager: &LightManager) -> Result<(), ()>{
+        return self.generate_instance_with(name, light_manager, LightAssignment::All);
+    }
+
+    pub fn generate_instance_with(&mut self, name: String, light_manager: &LightManager, assignment: LightAssignment) -> Result<(), ()>{
         if self.instances.contains_key(&name){
             return Err(());
         }
@@ -237,7 +245,9 @@ impl ProfileLoader{
                     Some(x) => x,
                     None => return Err(())
                 };
-                let mut p = Profile::new( name.clone(), false, false, light_manager.new_template());
+                let lights = light_manager.filtered(&assignment.resolve(light_manager));
+                let mut p = Profile::new( name.clone(), false, false, lights.new_template());
+                p.set_assignment(assignment);
                 interface.created(&mut p);
                 self.instances.insert(name, p);
                 debug!("Created instance of {}, with name {}", interface.profile_name(), &self.name);
@@ -259,6 +269,22 @@ impl ProfileLoader{
         return Ok(());
     }
 
+    pub fn set_instance_assignment(&mut self, name: String, assignment: LightAssignment, state: &LightManager) -> Result<(), ()>{
+        let instance = match self.instances.get_mut(&name){
+            None => return Err(()),
+            Some(x) => x
+        };
+        instance.set_assignment(assignment);
+        let assigned = state.filtered(&instance.get_assignment().resolve(state));
+        let diff = instance.m().sync_structure(&assigned);
+        if let Some(x) = &self.interface{
+            if !diff.is_empty(){
+                x.structure_changed(instance, &diff);
+            }
+        }
+        return Ok(());
+    }
+
     pub fn get_instance_names(&self) -> Vec<String>{
         return self.instances.keys().cloned().collect();
     }
@@ -302,13 +328,22 @@ impl ProfileLoader{
         for (_, i) in &mut self.instances{
             if i.is_on(){
                 interface.update(i);
+                if !i.is_layered() && i.get_lights().has_transparency(){
+                    i.set_layered(true);
+                }
             }
         }
     }
 
     pub fn update_light_structure(&mut self, state: &LightManager){
         for (_, instance) in &mut self.instances{
-            instance.m().sync_structure(state);
+            let assigned = state.filtered(&instance.get_assignment().resolve(state));
+            let diff = instance.m().sync_structure(&assigned);
+            if let Some(x) = &self.interface{
+                if !diff.is_empty(){
+                    x.structure_changed(instance, &diff);
+                }
+            }
         }
     }
 
@@ -0,0 +1,204 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde_json::json;
+
+use crate::fseq::FseqWriter;
+use crate::managers::light_manager::LightManager;
+use super::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMapEntry{
+    pub id: u32,
+    pub name: String,
+    pub start_channel: u32,
+    pub channel_count: u32
+}
+
+// Three channels per light in id order, matching `FseqPlayer`: strips and
+// matrices with a start channel sit there, the rest follow the highest channel used so far
+pub fn channel_map(lights: &LightManager) -> Vec<ChannelMapEntry>{
+    let layout = layout(lights);
+    let mut out: Vec<ChannelMapEntry> = Vec::with_capacity(layout.len());
+    let mut next: u32 = 0;
+    for i in layout{
+        let count = i.length as u32 * 3;
+        let start = match lights.get_light(i.id).and_then(|x| x.get_start_channel()){
+            Some(x) => x,
+            None => next
+        };
+        out.push(ChannelMapEntry { id: i.id, name: i.name, start_channel: start, channel_count: count });
+        next = next.max(start.saturating_add(count));
+    }
+    return out;
+}
+
+fn frame(map: &Vec<ChannelMapEntry>, lights: &LightManager) -> Vec<u8>{
+    let size = map.iter().map(|x| x.start_channel.saturating_add(x.channel_count)).max().unwrap_or(0);
+    let mut out: Vec<u8> = vec![0; size as usize];
+    for entry in map{
+        if let Some(x) = lights.get_light(entry.id){
+            let mut c = entry.start_channel as usize;
+            for i in x._get_lights(){
+                out[c..c + 3].copy_from_slice(&i.get_output_color().to_bytes());
+                c += 3;
+            }
+        }
+    }
+    return out;
+}
+
+// Records composited frames and writes them as an fseq v2 file on finish,
+// with the channel map saved next to it as `<path>.channels.json`.
+// A layout change mid-recording closes the file and continues in `<name>-1.fseq`, `<name>-2.fseq`...
+pub struct FseqRecorder{
+    path: String,
+    step_ms: u8,
+    part: u32,
+    writer: Option<FseqWriter>,
+    map: Vec<ChannelMapEntry>
+}
+
+impl FseqRecorder{
+    pub fn new(path: String, step_ms: u8) -> FseqRecorder{
+        return FseqRecorder { path, step_ms, part: 0, writer: None, map: Vec::new() };
+    }
+    pub fn get_channel_map(&self) -> &Vec<ChannelMapEntry>{
+        return &self.map;
+    }
+
+    // The file currently being recorded
+    pub fn get_path(&self) -> String{
+        if self.part == 0{
+            return self.path.clone();
+        }
+        let path = Path::new(&self.path);
+        let stem = path.file_stem().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+        let name = match path.extension(){
+            Some(x) => format!("{}-{}.{}", stem, self.part, x.to_string_lossy()),
+            None => format!("{}-{}", stem, self.part)
+        };
+        return path.with_file_name(name).to_string_lossy().to_string();
+    }
+
+    fn write_channel_map(&self) -> io::Result<()>{
+        let entries: Vec<serde_json::Value> = self.map.iter().map(|x| json!({
+            "id": x.id,
+            "name": x.name,
+            "start_channel": x.start_channel,
+            "channel_count": x.channel_count
+        })).collect();
+        return fs::write(format!("{}.channels.json", self.get_path()), serde_json::Value::Array(entries).to_string());
+    }
+}
+
+impl FrameSink for FseqRecorder{
+    fn write_frame(&mut self, lights: &LightManager) -> io::Result<()>{
+        let map = channel_map(lights);
+        if self.writer.is_some() && map != self.map{
+            self.finish()?;
+            self.part += 1;
+            self.writer = None;
+        }
+        if self.writer.is_none(){
+            self.map = map;
+            let channels = self.map.iter().map(|x| x.start_channel.saturating_add(x.channel_count)).max().unwrap_or(0);
+            let mut writer = FseqWriter::new(channels, self.step_ms);
+            writer.add_variable_header(*b"sp", "light-controller".to_string());
+            self.writer = Some(writer);
+        }
+        if let Some(x) = self.writer.as_mut(){
+            x.add_frame(frame(&self.map, lights));
+        }
+        return Ok(());
+    }
+    fn finish(&mut self) -> io::Result<()>{
+        let writer = match &self.writer{
+            None => return Err(io::Error::new(io::ErrorKind::Other, "No frames recorded")),
+            Some(x) => x
+        };
+        writer.save(&self.get_path())?;
+        return self.write_channel_map();
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::fseq::FseqFile;
+    use crate::structs::color::Color;
+
+    fn t_path(name: &str) -> String{
+        let dir = std::env::temp_dir().join(format!("fseq-recorder-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        return dir.join("show.fseq").to_string_lossy().to_string();
+    }
+
+    fn t_fill(lights: &mut LightManager, color: Color){
+        for id in lights.get_all_ids(){
+            for i in lights.get_light_mut(id).unwrap()._get_lights_mut(){
+                i.set_color(color);
+                i.set_transp(0);
+            }
+        }
+    }
+
+    #[test]
+    fn recordings_read_back_with_their_channel_map(){
+        let mut lights = LightManager::new();
+        let first = lights.add_light(LightStrip::new_enum("first".to_string(), 1, 2, RgbLight::default_enum()));
+        let mut placed = LightStrip::new("placed".to_string(), 2, 2, RgbLight::default_enum());
+        placed.set_start_channel(Some(30));
+        let placed = lights.add_light(LightingTypes::LightStrip(placed));
+        let bulb = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "porch".to_string())));
+
+        let path = t_path("round-trip");
+        let mut recorder = FseqRecorder::new(path.clone(), 25);
+        t_fill(&mut lights, Color::new(1, 2, 3));
+        recorder.write_frame(&lights).unwrap();
+        t_fill(&mut lights, Color::new(4, 5, 6));
+        recorder.write_frame(&lights).unwrap();
+        recorder.finish().unwrap();
+
+        let map = recorder.get_channel_map();
+        assert_eq!(map.iter().map(|x| (x.id, x.start_channel, x.channel_count)).collect::<Vec<(u32, u32, u32)>>(),
+            vec![(first, 0, 6), (placed, 30, 6), (bulb, 36, 3)]);
+        let file = FseqFile::open(&path).unwrap();
+        assert_eq!(file.get_channel_count(), 39);
+        assert_eq!(file.get_frame_count(), 2);
+        assert_eq!(file.get_step_ms(), 25);
+        for (frame, value) in [(0, [1, 2, 3]), (1, [4, 5, 6])]{
+            let data = file.get_frame(frame).unwrap();
+            for entry in map{
+                let range = entry.start_channel as usize..(entry.start_channel + entry.channel_count) as usize;
+                assert_eq!(data[range], value.repeat(entry.channel_count as usize / 3)[..]);
+            }
+            assert!(data[6..30].iter().all(|x| *x == 0));
+        }
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(format!("{}.channels.json", path)).unwrap()).unwrap();
+        assert_eq!(saved[1]["start_channel"], 30);
+        assert_eq!(saved[2]["name"], "porch");
+    }
+
+    #[test]
+    fn layout_changes_start_a_new_file(){
+        let mut lights = LightManager::new();
+        lights.add_light(LightStrip::new_enum("first".to_string(), 1, 2, RgbLight::default_enum()));
+        let path = t_path("layout-change");
+        let mut recorder = FseqRecorder::new(path.clone(), 25);
+        recorder.write_frame(&lights).unwrap();
+        recorder.write_frame(&lights).unwrap();
+
+        lights.add_light(LightStrip::new_enum("second".to_string(), 2, 4, RgbLight::default_enum()));
+        recorder.write_frame(&lights).unwrap();
+        recorder.finish().unwrap();
+
+        let next = recorder.get_path();
+        assert!(next.ends_with("show-1.fseq"));
+        let file = FseqFile::open(&path).unwrap();
+        assert_eq!((file.get_channel_count(), file.get_frame_count()), (6, 2));
+        let file = FseqFile::open(&next).unwrap();
+        assert_eq!((file.get_channel_count(), file.get_frame_count()), (18, 1));
+        assert!(Path::new(&format!("{}.channels.json", next)).exists());
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+
+use crate::managers::light_manager::LightManager;
+use crate::structs::light_primitive::*;
+use crate::structs::light_types::*;
+
+pub mod raw_stream;
+pub mod fseq_recorder;
+pub mod recorder;
+#[cfg(unix)]
+pub mod shm;
+
+pub trait FrameSink{
+    fn write_frame(&mut self, lights: &LightManager) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>{
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutEntry{
+    pub id: u32,
+    pub type_code: u8,
+    // Channels per pixel in `frame_channels`, 3 for rgb, 4 for rgbw, 5 for rgbcct
+    pub channels: u8,
+    pub length: usize,
+    pub name: String
+}
+
+pub fn type_code(light: &LightingTypes) -> u8{
+    return match light{
+        LightingTypes::LightStrip(_) => 0,
+        LightingTypes::Bulb(_) => 1,
+        LightingTypes::BulbGroup(_) => 2,
+        LightingTypes::LightMatrix(_) => 3
+    };
+}
+
+// The most channels a pixel can have, rgbcct
+pub const MAX_PIXEL_CHANNELS: usize = 5;
+
+// The widest pixel of a light, narrower pixels in a mixed group are padded to it
+pub fn pixel_channels(light: &LightingTypes) -> u8{
+    return light._get_lights().iter().map(|x| x.get_channels().len() as u8).max().unwrap_or(3);
+}
+
+// Every light in id order, the order frames are packed in
+pub fn layout(lights: &LightManager) -> Vec<LayoutEntry>{
+    let mut ids = lights.get_all_ids();
+    ids.sort();
+
+    let mut out: Vec<LayoutEntry> = Vec::with_capacity(ids.len());
+    for id in ids{
+        if let Some(x) = lights.get_light(id){
+            out.push(LayoutEntry { id, type_code: type_code(x), channels: pixel_channels(x), length: x.size(), name: x.get_name() });
+        }
+    }
+    return out;
+}
+
+pub fn frame_rgb(lights: &LightManager) -> Vec<u8>{
+    let mut out: Vec<u8> = Vec::new();
+    for entry in layout(lights){
+        if let Some(x) = lights.get_light(entry.id){
+            for i in x._get_lights(){
+                out.extend_from_slice(&i.get_output_color().to_bytes());
+            }
+        }
+    }
+    return out;
+}
+
+// Every pixel's device channels, `channels` bytes each as given by the layout,
+// so white leds get their own values instead of being folded into rgb
+pub fn frame_channels(lights: &LightManager) -> Vec<u8>{
+    let mut out: Vec<u8> = Vec::new();
+    for entry in layout(lights){
+        if let Some(x) = lights.get_light(entry.id){
+            for i in x._get_lights(){
+                let mut channels = i.get_channels();
+                channels.resize(entry.channels as usize, 0);
+                out.extend_from_slice(&channels);
+            }
+        }
+    }
+    return out;
+}
+
+// u32 count, then per light: u32 id, u8 type, u8 channels, u32 length, u16 name length, name
+pub fn encode_layout(layout: &Vec<LayoutEntry>) -> Vec<u8>{
+    let mut out: Vec<u8> = Vec::new();
+    out.extend_from_slice(&(layout.len() as u32).to_le_bytes());
+    for i in layout{
+        let name = i.name.as_bytes();
+        let name = &name[..name.len().min(u16::MAX as usize)];
+        out.extend_from_slice(&i.id.to_le_bytes());
+        out.push(i.type_code);
+        out.push(i.channels);
+        out.extend_from_slice(&(i.length as u32).to_le_bytes());
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(name);
+    }
+    return out;
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::structs::color::Color;
+
+    #[test]
+    fn frames_carry_white_channels(){
+        let mut lights = LightManager::new();
+        let rgb = lights.add_light(LightStrip::new_enum("rgb".to_string(), 1, 1, RgbLight::default_enum()));
+        let rgbw = lights.add_light(LightStrip::new_enum("rgbw".to_string(), 2, 2, RgbwLight::default_enum()));
+        let mut group = BulbGroup::new("group".to_string());
+        group.add_bulb(Bulb::new("10.0.0.1".to_string(), "a".to_string()));
+        group.add_bulb(Bulb::new("10.0.0.2".to_string(), "b".to_string()));
+        let group = lights.add_light(LightingTypes::BulbGroup(group));
+        *lights.get_light_mut(group).unwrap()._get_lights_mut()[1] = RgbcctLight::default_enum();
+        for id in lights.get_all_ids(){
+            lights.get_light_mut(id).unwrap().set_color(Color::new(200, 100, 50));
+        }
+
+        let entries = layout(&lights);
+        assert_eq!(entries.iter().map(|x| (x.id, x.channels, x.length)).collect::<Vec<(u32, u8, usize)>>(),
+            vec![(rgb, 3, 1), (rgbw, 4, 2), (group, 5, 2)]);
+        assert_eq!(frame_channels(&lights), vec![
+            200, 100, 50,
+            150, 50, 0, 50, 150, 50, 0, 50,
+            200, 100, 50, 0, 0, 150, 50, 0, 25, 25
+        ]);
+        assert_eq!(frame_rgb(&lights).len(), 5 * 3);
+
+        let encoded = encode_layout(&entries);
+        assert_eq!(u32::from_le_bytes(encoded[0..4].try_into().unwrap()), 3);
+        assert_eq!(&encoded[4..14], &[0, 0, 0, 0, 0, 3, 1, 0, 0, 0]);
+    }
+}
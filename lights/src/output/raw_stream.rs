@@ -0,0 +1,70 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+use crate::managers::light_manager::LightManager;
+use super::*;
+
+// Stream format, all integers little endian:
+//   "LAYT" u32 count, then per light: u32 id, u8 type, u8 channels, u32 length, u16 name length, name
+//   "FRAM" u64 frame, u64 unix time in microseconds, u32 byte count, then every
+//          pixel's channels in layout order, `channels` bytes each (r, g, b, w / ww, cw)
+// A layout record is written before the first frame and whenever the layout changes.
+pub struct RawStreamOutput{
+    writer: Box<dyn Write>,
+    layout: Option<Vec<LayoutEntry>>
+}
+
+impl RawStreamOutput{
+    pub fn new(writer: Box<dyn Write>) -> RawStreamOutput{
+        return RawStreamOutput { writer, layout: None };
+    }
+    pub fn stdout() -> RawStreamOutput{
+        return RawStreamOutput::new(Box::new(io::stdout()));
+    }
+    pub fn fifo(path: &str) -> io::Result<RawStreamOutput>{
+        let file = OpenOptions::new().write(true).open(path)?;
+        return Ok(RawStreamOutput::new(Box::new(file)));
+    }
+    #[cfg(unix)]
+    pub fn unix_socket(path: &str) -> io::Result<RawStreamOutput>{
+        let stream = UnixStream::connect(path)?;
+        return Ok(RawStreamOutput::new(Box::new(stream)));
+    }
+
+    fn write_layout(&mut self, layout: &Vec<LayoutEntry>) -> io::Result<()>{
+        self.writer.write_all(b"LAYT")?;
+        return self.writer.write_all(&encode_layout(layout));
+    }
+}
+
+impl FrameSink for RawStreamOutput{
+    fn write_frame(&mut self, lights: &LightManager) -> io::Result<()>{
+        let current = layout(lights);
+        if self.layout.as_ref() != Some(&current){
+            self.write_layout(&current)?;
+            self.layout = Some(current);
+        }
+
+        let time = match SystemTime::now().duration_since(UNIX_EPOCH){
+            Ok(x) => x.as_micros() as u64,
+            Err(_) => 0
+        };
+        let data = frame_channels(lights);
+
+        let mut out: Vec<u8> = Vec::with_capacity(data.len() + 24);
+        out.extend_from_slice(b"FRAM");
+        out.extend_from_slice(&lights.get_frame().to_le_bytes());
+        out.extend_from_slice(&time.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        self.writer.write_all(&out)?;
+        return self.writer.flush();
+    }
+    fn finish(&mut self) -> io::Result<()>{
+        return self.writer.flush();
+    }
+}
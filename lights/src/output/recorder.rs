@@ -0,0 +1,245 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use crate::managers::light_manager::LightManager;
+use crate::structs::color::Color;
+use super::*;
+
+const SWATCH: usize = 8;
+const GAP: usize = 2;
+const GIF_SCALE: usize = 4;
+const GAP_COLOR: [u8; 3] = [32, 32, 32];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordFormat{
+    Png,
+    Gif
+}
+
+// One recorded frame, strips keep one color per pixel and bulbs one per bulb
+struct RecordedFrame{
+    strips: Vec<Vec<Color>>,
+    bulbs: Vec<Color>
+}
+
+pub struct FrameRecorder{
+    path: String,
+    format: RecordFormat,
+    frame_delay_ms: u32,
+    frames: Vec<RecordedFrame>
+}
+
+impl FrameRecorder{
+    pub fn new(path: String, format: RecordFormat) -> FrameRecorder{
+        return FrameRecorder { path, format, frame_delay_ms: 33, frames: Vec::new() };
+    }
+    pub fn from_path(path: String) -> FrameRecorder{
+        let format = match Path::new(&path).extension().and_then(|x| x.to_str()){
+            Some(x) if x.eq_ignore_ascii_case("gif") => RecordFormat::Gif,
+            _ => RecordFormat::Png
+        };
+        return FrameRecorder::new(path, format);
+    }
+    pub fn set_frame_delay(&mut self, ms: u32){
+        self.frame_delay_ms = ms;
+    }
+    pub fn frame_count(&self) -> usize{
+        return self.frames.len();
+    }
+
+    fn strip_width(&self) -> usize{
+        return self.frames.iter()
+            .flat_map(|f| f.strips.iter().map(|x| x.len()))
+            .max().unwrap_or(0);
+    }
+
+    // Strips side by side, bulbs as swatches after them, one row per frame
+    fn write_png(&self) -> io::Result<()>{
+        let first = match self.frames.first(){
+            None => return Err(io::Error::new(io::ErrorKind::Other, "No frames recorded")),
+            Some(x) => x
+        };
+        let mut widths: Vec<usize> = first.strips.iter().map(|x| x.len()).collect();
+        widths.extend(first.bulbs.iter().map(|_| SWATCH));
+        let width = (widths.iter().sum::<usize>() + GAP * widths.len().saturating_sub(1)).max(1);
+        let height = self.frames.len();
+
+        let mut data: Vec<u8> = Vec::with_capacity(width * height * 3);
+        for frame in &self.frames{
+            let mut row: Vec<u8> = Vec::with_capacity(width * 3);
+            let blocks = frame.strips.iter().cloned()
+                .chain(frame.bulbs.iter().map(|x| vec![*x; SWATCH]));
+            for (index, block) in blocks.enumerate(){
+                if index > 0{
+                    for _ in 0..GAP{
+                        row.extend_from_slice(&GAP_COLOR);
+                    }
+                }
+                for i in block{
+                    row.extend_from_slice(&i.to_bytes());
+                }
+            }
+            row.resize(width * 3, 0);
+            data.extend(row);
+        }
+
+        let file = BufWriter::new(File::create(&self.path)?);
+        let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = match encoder.write_header(){
+            Ok(x) => x,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e.to_string()))
+        };
+        return match writer.write_image_data(&data){
+            Ok(_) => Ok(()),
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e.to_string()))
+        };
+    }
+
+    // Each strip is a row of the preview, bulbs share a row of swatches below
+    fn write_gif(&self) -> io::Result<()>{
+        let strip_rows = self.frames.first().map_or(0, |x| x.strips.len());
+        let bulbs = self.frames.first().map_or(0, |x| x.bulbs.len());
+        let rows = strip_rows + if bulbs > 0 {1} else {0};
+
+        let width = (self.strip_width() * GIF_SCALE).max(bulbs * (SWATCH + GAP) * GIF_SCALE).max(1);
+        let height = (rows * (SWATCH + GAP) * GIF_SCALE).max(1);
+        if width > u16::MAX as usize || height > u16::MAX as usize{
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Preview is too large for a gif"));
+        }
+
+        let file = BufWriter::new(File::create(&self.path)?);
+        let mut encoder = match gif::Encoder::new(file, width as u16, height as u16, &[]){
+            Ok(x) => x,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e.to_string()))
+        };
+        if let Err(e) = encoder.set_repeat(gif::Repeat::Infinite){
+            return Err(io::Error::new(io::ErrorKind::Other, e.to_string()));
+        }
+
+        let row_height = (SWATCH + GAP) * GIF_SCALE;
+        for frame in &self.frames{
+            let mut data: Vec<u8> = vec![0; width * height * 3];
+            let mut paint = |x: usize, y: usize, w: usize, h: usize, c: [u8; 3]|{
+                for py in y..(y + h).min(height){
+                    for px in x..(x + w).min(width){
+                        let i = (py * width + px) * 3;
+                        data[i..i + 3].copy_from_slice(&c);
+                    }
+                }
+            };
+            for (row, strip) in frame.strips.iter().enumerate(){
+                for (index, i) in strip.iter().enumerate(){
+                    paint(index * GIF_SCALE, row * row_height, GIF_SCALE, SWATCH * GIF_SCALE, i.to_bytes());
+                }
+            }
+            for (index, i) in frame.bulbs.iter().enumerate(){
+                paint(index * (SWATCH + GAP) * GIF_SCALE, strip_rows * row_height, SWATCH * GIF_SCALE, SWATCH * GIF_SCALE, i.to_bytes());
+            }
+
+            let mut f = gif::Frame::from_rgb_speed(width as u16, height as u16, &data, 10);
+            // Gif delays count hundredths of a second in a u16
+            f.delay = (self.frame_delay_ms.saturating_add(5) / 10).min(u16::MAX as u32) as u16;
+            if let Err(e) = encoder.write_frame(&f){
+                return Err(io::Error::new(io::ErrorKind::Other, e.to_string()));
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl FrameSink for FrameRecorder{
+    fn write_frame(&mut self, lights: &LightManager) -> io::Result<()>{
+        let mut frame = RecordedFrame { strips: Vec::new(), bulbs: Vec::new() };
+        for entry in layout(lights){
+            let light = match lights.get_light(entry.id){
+                None => continue,
+                Some(x) => x
+            };
+            let colors: Vec<Color> = light._get_lights().iter().map(|x| x.get_output_color()).collect();
+            match light{
+                LightingTypes::LightStrip(_) | LightingTypes::LightMatrix(_) => frame.strips.push(colors),
+                _ => frame.bulbs.extend(colors)
+            }
+        }
+        self.frames.push(frame);
+        return Ok(());
+    }
+    fn finish(&mut self) -> io::Result<()>{
+        return match self.format{
+            RecordFormat::Png => self.write_png(),
+            RecordFormat::Gif => self.write_gif()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn t_path(name: &str) -> String{
+        let dir = std::env::temp_dir().join(format!("frame-recorder-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        return dir.join(name).to_string_lossy().to_string();
+    }
+
+    // Strips of 10 and 4 lights and two bulbs
+    fn t_record(path: &str, frames: usize) -> FrameRecorder{
+        let mut lights = LightManager::new();
+        let strip = lights.add_light(LightStrip::new_enum("a".to_string(), 1, 10, RgbLight::default_enum()));
+        lights.add_light(LightStrip::new_enum("b".to_string(), 2, 4, RgbLight::default_enum()));
+        lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "c".to_string())));
+        lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.2".to_string(), "d".to_string())));
+        lights.get_light_mut(strip).unwrap().set_color(Color::new(255, 0, 0));
+
+        let mut recorder = FrameRecorder::from_path(path.to_string());
+        recorder.set_frame_delay(50);
+        for _ in 0..frames{
+            recorder.write_frame(&lights).unwrap();
+        }
+        recorder.finish().unwrap();
+        return recorder;
+    }
+
+    #[test]
+    fn png_rows_are_frames(){
+        let path = t_path("preview.png");
+        assert_eq!(t_record(&path, 3).format, RecordFormat::Png);
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        // 10 + 4 strip pixels, two 8 pixel swatches and three gaps of 2
+        assert_eq!((info.width, info.height), (36, 3));
+        assert_eq!(&data[0..3], &[255, 0, 0]);
+        assert_eq!(&data[30..33], &GAP_COLOR);
+        assert_eq!(&data[36..39], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn gif_frames_keep_their_size_and_delay(){
+        let path = t_path("preview.gif");
+        assert_eq!(t_record(&path, 2).format, RecordFormat::Gif);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        // The widest of 10 strip pixels or two swatches, and a row each for the strips and bulbs
+        assert_eq!((decoder.width(), decoder.height()), (2 * (SWATCH + GAP) as u16 * GIF_SCALE as u16, 3 * (SWATCH + GAP) as u16 * GIF_SCALE as u16));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap(){
+            assert_eq!((frame.width, frame.height, frame.delay), (80, 120, 5));
+            frames += 1;
+        }
+        assert_eq!(frames, 2);
+    }
+
+    #[test]
+    fn empty_recordings_fail(){
+        let mut recorder = FrameRecorder::from_path(t_path("empty.png"));
+        assert!(recorder.finish().is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::ffi::CString;
+use std::io;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::managers::light_manager::LightManager;
+use super::*;
+
+// Shared memory layout, all integers little endian:
+//   header (64 bytes)
+//     0  magic "LCSHMRB1"
+//     8  u32 version, u32 slot count
+//     16 u64 slot data capacity in bytes
+//     24 u64 layout seqlock, odd while the layout table is being written
+//     32 u64 index of the latest complete frame, starts at 0 for "none yet"
+//     40 u64 layout table offset, 48 u64 layout table capacity, 56 u64 first slot offset
+//   layout table, encoded like `encode_layout`
+//   slots, each a 32 byte header followed by the frame, packed like `frame_channels`
+//     0  u64 seqlock, odd while the slot is being written
+//     8  u64 frame, 16 u64 unix time in microseconds, 24 u32 byte count, u32 padding
+// Frame n is stored in slot n % slot count. Readers copy a slot and retry if
+// its sequence number was odd or changed while reading.
+const MAGIC: &[u8; 8] = b"LCSHMRB1";
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 64;
+const SLOT_HEADER_SIZE: usize = 32;
+const LAYOUT_CAPACITY: usize = 64 * 1024;
+
+pub struct ShmRingOutput{
+    name: CString,
+    ptr: *mut u8,
+    size: usize,
+    slots: usize,
+    slot_capacity: usize,
+    written: u64,
+    layout: Option<Vec<LayoutEntry>>
+}
+
+impl ShmRingOutput{
+    // `max_lights` bounds the number of pixels a single frame can hold
+    pub fn new(name: &str, slots: usize, max_lights: usize) -> io::Result<ShmRingOutput>{
+        let name = if name.starts_with('/') {name.to_string()} else {format!("/{}", name)};
+        let name = match CString::new(name){
+            Ok(x) => x,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid shared memory name"))
+        };
+        let slots = slots.max(2);
+        let slot_capacity = (max_lights * MAX_PIXEL_CHANNELS).div_ceil(8) * 8;
+        let size = HEADER_SIZE + LAYOUT_CAPACITY + slots * (SLOT_HEADER_SIZE + slot_capacity);
+
+        let ptr = unsafe{
+            let fd = libc::shm_open(name.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o644);
+            if fd < 0{
+                return Err(io::Error::last_os_error());
+            }
+            if libc::ftruncate(fd, size as libc::off_t) != 0{
+                let e = io::Error::last_os_error();
+                libc::close(fd);
+                return Err(e);
+            }
+            let ptr = libc::mmap(ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd, 0);
+            libc::close(fd);
+            if ptr == libc::MAP_FAILED{
+                return Err(io::Error::last_os_error());
+            }
+            ptr as *mut u8
+        };
+
+        let out = ShmRingOutput { name, ptr, size, slots, slot_capacity, written: 0, layout: None };
+        out.write_header();
+        return Ok(out);
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64{
+        return unsafe{ &*(self.ptr.add(offset) as *const AtomicU64) };
+    }
+    fn write_bytes(&self, offset: usize, data: &[u8]){
+        unsafe{
+            ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.add(offset), data.len());
+        }
+    }
+
+    fn write_header(&self){
+        unsafe{
+            ptr::write_bytes(self.ptr, 0, HEADER_SIZE);
+        }
+        self.write_bytes(0, MAGIC);
+        self.write_bytes(8, &VERSION.to_le_bytes());
+        self.write_bytes(12, &(self.slots as u32).to_le_bytes());
+        self.write_bytes(16, &(self.slot_capacity as u64).to_le_bytes());
+        self.write_bytes(40, &(HEADER_SIZE as u64).to_le_bytes());
+        self.write_bytes(48, &(LAYOUT_CAPACITY as u64).to_le_bytes());
+        self.write_bytes(56, &((HEADER_SIZE + LAYOUT_CAPACITY) as u64).to_le_bytes());
+        fence(Ordering::Release);
+    }
+
+    fn write_layout(&mut self, layout: &Vec<LayoutEntry>) -> io::Result<()>{
+        let data = encode_layout(layout);
+        if data.len() > LAYOUT_CAPACITY{
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Light layout does not fit in shared memory"));
+        }
+        let seq = self.atomic(24);
+        seq.fetch_add(1, Ordering::AcqRel);
+        fence(Ordering::Release);
+        self.write_bytes(HEADER_SIZE, &data);
+        fence(Ordering::Release);
+        self.atomic(24).fetch_add(1, Ordering::AcqRel);
+        return Ok(());
+    }
+}
+
+impl FrameSink for ShmRingOutput{
+    fn write_frame(&mut self, lights: &LightManager) -> io::Result<()>{
+        let current = layout(lights);
+        if self.layout.as_ref() != Some(&current){
+            self.write_layout(&current)?;
+            self.layout = Some(current);
+        }
+
+        let data = frame_channels(lights);
+        if data.len() > self.slot_capacity{
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Frame does not fit in a shared memory slot"));
+        }
+        let time = match SystemTime::now().duration_since(UNIX_EPOCH){
+            Ok(x) => x.as_micros() as u64,
+            Err(_) => 0
+        };
+
+        self.written += 1;
+        let slot = (self.written as usize) % self.slots;
+        let offset = HEADER_SIZE + LAYOUT_CAPACITY + slot * (SLOT_HEADER_SIZE + self.slot_capacity);
+
+        self.atomic(offset).fetch_add(1, Ordering::AcqRel);
+        fence(Ordering::Release);
+        self.write_bytes(offset + 8, &lights.get_frame().to_le_bytes());
+        self.write_bytes(offset + 16, &time.to_le_bytes());
+        self.write_bytes(offset + 24, &(data.len() as u32).to_le_bytes());
+        self.write_bytes(offset + SLOT_HEADER_SIZE, &data);
+        fence(Ordering::Release);
+        self.atomic(offset).fetch_add(1, Ordering::AcqRel);
+
+        self.atomic(32).store(self.written, Ordering::Release);
+        return Ok(());
+    }
+}
+
+impl Drop for ShmRingOutput{
+    fn drop(&mut self){
+        unsafe{
+            libc::munmap(self.ptr as *mut libc::c_void, self.size);
+            libc::shm_unlink(self.name.as_ptr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::structs::color::Color;
+
+    fn t_output(name: &str, slots: usize, max_lights: usize) -> ShmRingOutput{
+        return ShmRingOutput::new(&format!("lights-test-{}-{}", std::process::id(), name), slots, max_lights).unwrap();
+    }
+
+    fn t_bytes(out: &ShmRingOutput, offset: usize, len: usize) -> Vec<u8>{
+        return unsafe{ std::slice::from_raw_parts(out.ptr.add(offset), len).to_vec() };
+    }
+    fn t_u64(out: &ShmRingOutput, offset: usize) -> u64{
+        return u64::from_le_bytes(t_bytes(out, offset, 8).try_into().unwrap());
+    }
+
+    // Reads a slot the way a client would, retrying while it is being written
+    fn t_read_slot(out: &ShmRingOutput, slot: usize) -> (u64, Vec<u8>){
+        let offset = HEADER_SIZE + LAYOUT_CAPACITY + slot * (SLOT_HEADER_SIZE + out.slot_capacity);
+        loop{
+            let before = out.atomic(offset).load(Ordering::Acquire);
+            if before % 2 == 1{
+                continue;
+            }
+            let frame = t_u64(out, offset + 8);
+            let count = u32::from_le_bytes(t_bytes(out, offset + 24, 4).try_into().unwrap()) as usize;
+            let data = t_bytes(out, offset + SLOT_HEADER_SIZE, count);
+            fence(Ordering::Acquire);
+            if out.atomic(offset).load(Ordering::Acquire) == before{
+                return (frame, data);
+            }
+        }
+    }
+
+    fn t_lights(length: usize) -> LightManager{
+        let mut lights = LightManager::new();
+        lights.add_light(LightStrip::new_enum("strip".to_string(), 1, length, RgbLight::default_enum()));
+        return lights;
+    }
+
+    #[test]
+    fn frames_round_trip_through_the_ring(){
+        let mut out = t_output("ring", 2, 4);
+        assert_eq!(t_bytes(&out, 0, 8), MAGIC.to_vec());
+        assert_eq!(t_bytes(&out, 8, 8), [VERSION.to_le_bytes(), 2u32.to_le_bytes()].concat());
+        assert_eq!(t_u64(&out, 16), 24);
+        assert_eq!(t_u64(&out, 32), 0);
+
+        let mut lights = t_lights(2);
+        for n in 1..=3u8{
+            for id in lights.get_all_ids(){
+                lights.get_light_mut(id).unwrap().set_color(Color::new(n, 0, 0));
+            }
+            lights.commit_frame();
+            out.write_frame(&lights).unwrap();
+            let (frame, data) = t_read_slot(&out, n as usize % 2);
+            assert_eq!(frame, n as u64);
+            assert_eq!(data, vec![n, 0, 0, n, 0, 0]);
+            assert_eq!(t_u64(&out, 32), n as u64);
+        }
+        // Frame 2 is still in the other slot, frame 1 was overwritten by 3
+        assert_eq!(t_read_slot(&out, 0).0, 2);
+
+        // The layout is written once, leaving its seqlock even
+        assert_eq!(t_u64(&out, 24), 2);
+        let layout = t_bytes(&out, HEADER_SIZE, LAYOUT_CAPACITY);
+        assert_eq!(layout[..encode_layout(&super::layout(&lights)).len()], encode_layout(&super::layout(&lights))[..]);
+        lights.add_light(LightStrip::new_enum("second".to_string(), 2, 1, RgbLight::default_enum()));
+        out.write_frame(&lights).unwrap();
+        assert_eq!(t_u64(&out, 24), 4);
+    }
+
+    #[test]
+    fn oversized_frames_and_layouts_are_rejected(){
+        // Room for two pixels of five channels, rounded up to 16 bytes
+        let mut out = t_output("frame-overflow", 2, 2);
+        assert_eq!(out.slot_capacity, 16);
+        assert!(out.write_frame(&t_lights(5)).is_ok());
+        assert!(out.write_frame(&t_lights(6)).is_err());
+
+        // Each name is cut to u16::MAX bytes, so two long ones overflow the table
+        let mut out = t_output("layout-overflow", 2, 4);
+        let mut lights = LightManager::new();
+        lights.add_light(LightStrip::new_enum("a".repeat(40000), 1, 1, RgbLight::default_enum()));
+        assert!(out.write_frame(&lights).is_ok());
+        lights.add_light(LightStrip::new_enum("b".repeat(40000), 1, 1, RgbLight::default_enum()));
+        assert!(encode_layout(&super::layout(&lights)).len() > LAYOUT_CAPACITY);
+        assert!(out.write_frame(&lights).is_err());
+        assert_eq!(t_u64(&out, 24), 2);
+    }
+}
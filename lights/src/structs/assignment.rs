@@ -0,0 +1,76 @@
+use crate::managers::light_manager::LightManager;
+use super::light_primitive::*;
+use super::selector::Selector;
+
+// Which lights an instance draws on, resolved against the system's lights
+// whenever the structure changes so that later additions are picked up
+#[derive(Debug, Clone, PartialEq)]
+pub enum LightAssignment{
+    All,
+    Uids(Vec<String>),
+    Names(Vec<String>),
+    Segments(Vec<String>),
+    Tags(Vec<String>),
+    Rooms(Vec<String>),
+    Selector(Selector)
+}
+
+impl Default for LightAssignment{
+    fn default() -> LightAssignment{
+        return LightAssignment::All;
+    }
+}
+
+impl LightAssignment{
+    // Selectors are parsed up front so a typo is reported instead of assigning nothing
+    pub fn selector(text: &str) -> Result<LightAssignment, String>{
+        return Ok(LightAssignment::Selector(Selector::parse(text)?));
+    }
+
+    pub fn resolve(&self, lights: &LightManager) -> Vec<u32>{
+        let mut out: Vec<u32> = match self{
+            LightAssignment::All => lights.get_all_ids(),
+            LightAssignment::Uids(x) => x.iter()
+                .filter_map(|uid| lights.get_id_by_uid(uid))
+                .collect(),
+            LightAssignment::Names(x) => lights.get_all_ids().into_iter()
+                .filter(|id| x.contains(&lights.get_light(*id).unwrap().get_name()))
+                .collect(),
+            LightAssignment::Segments(x) => x.iter()
+                .filter_map(|name| lights.get_segment(name))
+                .flat_map(|s| s.get_uids())
+                .filter_map(|uid| lights.get_id_by_uid(&uid))
+                .collect(),
+            LightAssignment::Tags(x) => x.iter()
+                .flat_map(|tag| lights.get_ids_with_tag(tag))
+                .collect(),
+            LightAssignment::Rooms(x) => x.iter()
+                .flat_map(|room| lights.get_room_ids(room))
+                .collect(),
+            // Instances hold whole lights, so any light the selector touches is assigned
+            LightAssignment::Selector(x) => x.resolve_ids(lights)
+        };
+        out.sort();
+        out.dedup();
+        return out;
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use super::super::light_types::*;
+
+    #[test]
+    fn selectors_are_checked_when_assigned(){
+        let mut lights = LightManager::new();
+        let strip = lights.add_light(LightStrip::new_enum("strip".to_string(), 1, 10, RgbLight::default_enum()));
+        let bulb = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "porch".to_string())));
+        lights.add_tag(bulb, "accent".to_string());
+
+        assert!(LightAssignment::selector("tag:accent &").is_err());
+        assert!(LightAssignment::selector("colour:red").is_err());
+        let assignment = LightAssignment::selector("tag:accent | name:strip[0..2]").unwrap();
+        assert_eq!(assignment.resolve(&lights), vec![strip, bulb]);
+    }
+}
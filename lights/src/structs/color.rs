@@ -7,6 +7,95 @@ pub struct Color{
     green: u8,
     blue:u8
 }
+
+// Color in linear light, where adding and scaling behave like light does
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct LinearColor{
+    pub r: f32,
+    pub g: f32,
+    pub b: f32
+}
+// Hue in degrees, the rest from 0 to 1
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Hsv{
+    pub h: f32,
+    pub s: f32,
+    pub v: f32
+}
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Hsl{
+    pub h: f32,
+    pub s: f32,
+    pub l: f32
+}
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Oklab{
+    pub l: f32,
+    pub a: f32,
+    pub b: f32
+}
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Oklch{
+    pub l: f32,
+    pub c: f32,
+    pub h: f32
+}
+
+// Where interpolation happens. Hue spaces take the short way around the circle.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum ColorSpace{
+    Srgb,
+    Linear,
+    Hsv,
+    Hsl,
+    #[default]
+    Oklab,
+    Oklch
+}
+
+pub fn srgb_to_linear(c: f32) -> f32{
+    return if c <= 0.04045 {c / 12.92} else {((c + 0.055) / 1.055).powf(2.4)};
+}
+pub fn linear_to_srgb(c: f32) -> f32{
+    return if c <= 0.0031308 {12.92 * c} else {1.055 * c.powf(1.0 / 2.4) - 0.055};
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32{
+    return a + (b - a) * t;
+}
+fn lerp_hue(a: f32, b: f32, t: f32) -> f32{
+    let mut d = (b - a) % 360.0;
+    if d > 180.0{
+        d -= 360.0;
+    }else if d < -180.0{
+        d += 360.0;
+    }
+    return (a + d * t).rem_euclid(360.0);
+}
+fn to_u8(c: f32) -> u8{
+    return (c * 255.0).round().clamp(0.0, 255.0) as u8;
+}
+
+impl LinearColor{
+    pub fn new(r: f32, g: f32, b: f32) -> LinearColor{
+        return LinearColor { r, g, b };
+    }
+    pub fn add(&self, other: &LinearColor) -> LinearColor{
+        return LinearColor::new(self.r + other.r, self.g + other.g, self.b + other.b);
+    }
+    pub fn scale(&self, factor: f32) -> LinearColor{
+        return LinearColor::new(self.r * factor, self.g * factor, self.b * factor);
+    }
+    pub fn lerp(&self, other: &LinearColor, t: f32) -> LinearColor{
+        return LinearColor::new(lerp(self.r, other.r, t), lerp(self.g, other.g, t), lerp(self.b, other.b, t));
+    }
+    // Values outside 0 to 1 are clipped
+    pub fn to_color(&self) -> Color{
+        let c = |x: f32| to_u8(linear_to_srgb(x.clamp(0.0, 1.0)));
+        return Color::new(c(self.r), c(self.g), c(self.b));
+    }
+}
+
 impl Color{
     pub fn new(r: u8, g:u8, b:u8)->Color{
         Color {red: r, green: g, blue: b}
@@ -15,7 +104,365 @@ impl Color{
         let mut rng = rand::thread_rng();
         Color {red: rng.gen_range(0..=255), green: rng.gen_range(0..=255), blue: rng.gen_range(0..=255)}
     }
+    pub fn get_red(&self) -> u8{
+        return self.red;
+    }
+    pub fn set_red(&mut self, red: u8) -> &mut Self{
+        self.red = red;
+        return self;
+    }
+    pub fn get_green(&self) -> u8{
+        return self.green;
+    }
+    pub fn set_green(&mut self, green: u8) -> &mut Self{
+        self.green = green;
+        return self;
+    }
+    pub fn get_blue(&self) -> u8{
+        return self.blue;
+    }
+    pub fn set_blue(&mut self, blue: u8) -> &mut Self{
+        self.blue = blue;
+        return self;
+    }
+    pub fn to_bytes(&self) -> [u8; 3]{
+        return [self.red, self.green, self.blue];
+    }
+    pub fn mix(&self, other: &Color, ratio: u8) -> Color{
+        let f = |a: u8, b: u8| ((a as u16 * (255 - ratio as u16) + b as u16 * ratio as u16) / 255) as u8;
+        return Color::new(f(self.red, other.red), f(self.green, other.green), f(self.blue, other.blue));
+    }
     pub fn as_string(&self) -> String{
         return " ".on_truecolor(self.red, self.green, self.blue).to_string();
     }
-}
\ No newline at end of file
+
+    fn to_floats(self) -> [f32; 3]{
+        return [self.red as f32 / 255.0, self.green as f32 / 255.0, self.blue as f32 / 255.0];
+    }
+    fn from_floats(r: f32, g: f32, b: f32) -> Color{
+        return Color::new(to_u8(r), to_u8(g), to_u8(b));
+    }
+
+    pub fn to_linear(&self) -> LinearColor{
+        let [r, g, b] = self.to_floats();
+        return LinearColor::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+    }
+    pub fn from_linear(color: LinearColor) -> Color{
+        return color.to_color();
+    }
+
+    pub fn to_hsv(&self) -> Hsv{
+        let [r, g, b] = self.to_floats();
+        let max = r.max(g).max(b);
+        let d = max - r.min(g).min(b);
+        let s = if max > 0.0 {d / max} else {0.0};
+        return Hsv { h: hue(r, g, b, max, d), s, v: max };
+    }
+    pub fn from_hsv(hsv: Hsv) -> Color{
+        let c = hsv.v * hsv.s;
+        let (r, g, b) = from_hue(hsv.h, c);
+        let m = hsv.v - c;
+        return Color::from_floats(r + m, g + m, b + m);
+    }
+    pub fn to_hsl(&self) -> Hsl{
+        let [r, g, b] = self.to_floats();
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        let d = max - min;
+        let l = (max + min) / 2.0;
+        let s = if d == 0.0 {0.0} else {d / (1.0 - (2.0 * l - 1.0).abs())};
+        return Hsl { h: hue(r, g, b, max, d), s, l };
+    }
+    pub fn from_hsl(hsl: Hsl) -> Color{
+        let c = (1.0 - (2.0 * hsl.l - 1.0).abs()) * hsl.s;
+        let (r, g, b) = from_hue(hsl.h, c);
+        let m = hsl.l - c / 2.0;
+        return Color::from_floats(r + m, g + m, b + m);
+    }
+
+    // Oklab as published by Björn Ottosson
+    // The matrices are worked in f64, they carry more digits than an f32 holds
+    pub fn to_oklab(&self) -> Oklab{
+        let c = self.to_linear();
+        let (r, g, b) = (c.r as f64, c.g as f64, c.b as f64);
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        return Oklab {
+            l: (0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s) as f32,
+            a: (1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s) as f32,
+            b: (0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s) as f32
+        };
+    }
+    pub fn from_oklab(lab: Oklab) -> Color{
+        let (ll, a, b) = (lab.l as f64, lab.a as f64, lab.b as f64);
+        let l = (ll + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m = (ll - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s = (ll - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+        return LinearColor::new(
+            (4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s) as f32,
+            (-1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s) as f32,
+            (-0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s) as f32
+        ).to_color();
+    }
+    pub fn to_oklch(&self) -> Oklch{
+        let lab = self.to_oklab();
+        return Oklch { l: lab.l, c: lab.a.hypot(lab.b), h: lab.b.atan2(lab.a).to_degrees().rem_euclid(360.0) };
+    }
+    pub fn from_oklch(lch: Oklch) -> Color{
+        let h = lch.h.to_radians();
+        return Color::from_oklab(Oklab { l: lch.l, a: lch.c * h.cos(), b: lch.c * h.sin() });
+    }
+
+    // Adding and scaling are done in linear light, results are clipped
+    pub fn add(&self, other: &Color) -> Color{
+        return self.to_linear().add(&other.to_linear()).to_color();
+    }
+    pub fn scale(&self, factor: f32) -> Color{
+        return self.to_linear().scale(factor).to_color();
+    }
+    pub fn lerp(&self, other: &Color, t: f32, space: ColorSpace) -> Color{
+        let t = t.clamp(0.0, 1.0);
+        return match space{
+            ColorSpace::Srgb => {
+                let (a, b) = (self.to_floats(), other.to_floats());
+                Color::from_floats(lerp(a[0], b[0], t), lerp(a[1], b[1], t), lerp(a[2], b[2], t))
+            },
+            ColorSpace::Linear => self.to_linear().lerp(&other.to_linear(), t).to_color(),
+            ColorSpace::Hsv => {
+                let (a, b) = (self.to_hsv(), other.to_hsv());
+                Color::from_hsv(Hsv { h: lerp_hue(a.h, b.h, t), s: lerp(a.s, b.s, t), v: lerp(a.v, b.v, t) })
+            },
+            ColorSpace::Hsl => {
+                let (a, b) = (self.to_hsl(), other.to_hsl());
+                Color::from_hsl(Hsl { h: lerp_hue(a.h, b.h, t), s: lerp(a.s, b.s, t), l: lerp(a.l, b.l, t) })
+            },
+            ColorSpace::Oklab => {
+                let (a, b) = (self.to_oklab(), other.to_oklab());
+                Color::from_oklab(Oklab { l: lerp(a.l, b.l, t), a: lerp(a.a, b.a, t), b: lerp(a.b, b.b, t) })
+            },
+            ColorSpace::Oklch => {
+                let (a, b) = (self.to_oklch(), other.to_oklch());
+                Color::from_oklch(Oklch { l: lerp(a.l, b.l, t), c: lerp(a.c, b.c, t), h: lerp_hue(a.h, b.h, t) })
+            }
+        };
+    }
+
+    pub fn to_hex(&self) -> String{
+        return format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue);
+    }
+    pub fn to_css(&self) -> String{
+        return format!("rgb({}, {}, {})", self.red, self.green, self.blue);
+    }
+    // #rgb or #rrggbb, the # is optional
+    pub fn from_hex(text: &str) -> Result<Color, String>{
+        let hex = text.trim().trim_start_matches('#');
+        let digits: Vec<u8> = match hex.chars().map(|x| x.to_digit(16).map(|d| d as u8)).collect(){
+            Some(x) => x,
+            None => return Err(format!("Invalid hex color \"{}\"", text))
+        };
+        return match digits.len(){
+            3 => Ok(Color::new(digits[0] * 17, digits[1] * 17, digits[2] * 17)),
+            6 => Ok(Color::new(digits[0] * 16 + digits[1], digits[2] * 16 + digits[3], digits[4] * 16 + digits[5])),
+            _ => Err(format!("Invalid hex color \"{}\"", text))
+        };
+    }
+    pub fn from_name(name: &str) -> Option<Color>{
+        let name = name.trim().to_ascii_lowercase();
+        return NAMED_COLORS.iter()
+            .find(|x| x.0 == name)
+            .map(|x| Color::new((x.1 >> 16) as u8, (x.1 >> 8) as u8, x.1 as u8));
+    }
+    // Hex, a CSS color name, rgb(...) or hsl(...), commas or spaces between values
+    pub fn parse(text: &str) -> Result<Color, String>{
+        let text = text.trim();
+        if text.starts_with('#'){
+            return Color::from_hex(text);
+        }
+        if let Some(x) = Color::from_name(text){
+            return Ok(x);
+        }
+        let lower = text.to_ascii_lowercase();
+        let (function, args) = match lower.split_once('('){
+            Some((f, rest)) if rest.ends_with(')') => (f.trim(), &rest[..rest.len() - 1]),
+            _ => return Color::from_hex(text)
+        };
+        let values: Vec<&str> = args.split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .collect();
+        if values.len() < 3{
+            return Err(format!("Expected three values in \"{}\"", text));
+        }
+        let number = |x: &str, scale: f32| -> Result<f32, String>{
+            let (value, percent) = match x.strip_suffix('%'){
+                Some(v) => (v, true),
+                None => (x.trim_end_matches("deg"), false)
+            };
+            return match value.parse::<f32>(){
+                Ok(v) if percent => Ok(v / 100.0 * scale),
+                Ok(v) => Ok(v),
+                Err(_) => Err(format!("Invalid value \"{}\" in \"{}\"", x, text))
+            };
+        };
+        return match function{
+            "rgb" | "rgba" => Ok(Color::new(
+                number(values[0], 255.0)?.round().clamp(0.0, 255.0) as u8,
+                number(values[1], 255.0)?.round().clamp(0.0, 255.0) as u8,
+                number(values[2], 255.0)?.round().clamp(0.0, 255.0) as u8
+            )),
+            "hsl" | "hsla" => {
+                let s = if values[1].ends_with('%') {number(values[1], 1.0)?} else {number(values[1], 1.0)? / 100.0};
+                let l = if values[2].ends_with('%') {number(values[2], 1.0)?} else {number(values[2], 1.0)? / 100.0};
+                Ok(Color::from_hsl(Hsl { h: number(values[0], 1.0)?.rem_euclid(360.0), s: s.clamp(0.0, 1.0), l: l.clamp(0.0, 1.0) }))
+            },
+            _ => Err(format!("Unknown color function \"{}\"", function))
+        };
+    }
+}
+
+impl std::str::FromStr for Color{
+    type Err = String;
+    fn from_str(text: &str) -> Result<Color, String>{
+        return Color::parse(text);
+    }
+}
+
+fn hue(r: f32, g: f32, b: f32, max: f32, d: f32) -> f32{
+    if d == 0.0{
+        return 0.0;
+    }
+    let h = if max == r{
+        ((g - b) / d).rem_euclid(6.0)
+    }else if max == g{
+        (b - r) / d + 2.0
+    }else{
+        (r - g) / d + 4.0
+    };
+    return h * 60.0;
+}
+// Red, green and blue of a hue at chroma c, before the lightness offset
+fn from_hue(h: f32, c: f32) -> (f32, f32, f32){
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    return match h as u32{
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x)
+    };
+}
+
+// CSS Color Module Level 4 named colors
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff), ("antiquewhite", 0xfaebd7), ("aqua", 0x00ffff), ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff), ("beige", 0xf5f5dc), ("bisque", 0xffe4c4), ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd), ("blue", 0x0000ff), ("blueviolet", 0x8a2be2), ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887), ("cadetblue", 0x5f9ea0), ("chartreuse", 0x7fff00), ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50), ("cornflowerblue", 0x6495ed), ("cornsilk", 0xfff8dc), ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff), ("darkblue", 0x00008b), ("darkcyan", 0x008b8b), ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9), ("darkgreen", 0x006400), ("darkgrey", 0xa9a9a9), ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b), ("darkolivegreen", 0x556b2f), ("darkorange", 0xff8c00), ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000), ("darksalmon", 0xe9967a), ("darkseagreen", 0x8fbc8f), ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f), ("darkslategrey", 0x2f4f4f), ("darkturquoise", 0x00ced1), ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493), ("deepskyblue", 0x00bfff), ("dimgray", 0x696969), ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff), ("firebrick", 0xb22222), ("floralwhite", 0xfffaf0), ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff), ("gainsboro", 0xdcdcdc), ("ghostwhite", 0xf8f8ff), ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520), ("gray", 0x808080), ("green", 0x008000), ("greenyellow", 0xadff2f),
+    ("grey", 0x808080), ("honeydew", 0xf0fff0), ("hotpink", 0xff69b4), ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082), ("ivory", 0xfffff0), ("khaki", 0xf0e68c), ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5), ("lawngreen", 0x7cfc00), ("lemonchiffon", 0xfffacd), ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080), ("lightcyan", 0xe0ffff), ("lightgoldenrodyellow", 0xfafad2), ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90), ("lightgrey", 0xd3d3d3), ("lightpink", 0xffb6c1), ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa), ("lightskyblue", 0x87cefa), ("lightslategray", 0x778899), ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de), ("lightyellow", 0xffffe0), ("lime", 0x00ff00), ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6), ("magenta", 0xff00ff), ("maroon", 0x800000), ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd), ("mediumorchid", 0xba55d3), ("mediumpurple", 0x9370db), ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee), ("mediumspringgreen", 0x00fa9a), ("mediumturquoise", 0x48d1cc), ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970), ("mintcream", 0xf5fffa), ("mistyrose", 0xffe4e1), ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead), ("navy", 0x000080), ("oldlace", 0xfdf5e6), ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23), ("orange", 0xffa500), ("orangered", 0xff4500), ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa), ("palegreen", 0x98fb98), ("paleturquoise", 0xafeeee), ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5), ("peachpuff", 0xffdab9), ("peru", 0xcd853f), ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd), ("powderblue", 0xb0e0e6), ("purple", 0x800080), ("rebeccapurple", 0x663399),
+    ("red", 0xff0000), ("rosybrown", 0xbc8f8f), ("royalblue", 0x4169e1), ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072), ("sandybrown", 0xf4a460), ("seagreen", 0x2e8b57), ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d), ("silver", 0xc0c0c0), ("skyblue", 0x87ceeb), ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090), ("slategrey", 0x708090), ("snow", 0xfffafa), ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4), ("tan", 0xd2b48c), ("teal", 0x008080), ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347), ("turquoise", 0x40e0d0), ("violet", 0xee82ee), ("wheat", 0xf5deb3),
+    ("white", 0xffffff), ("whitesmoke", 0xf5f5f5), ("yellow", 0xffff00), ("yellowgreen", 0x9acd32)
+];
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // A few colors that exercise every hue sextant and the grey axis
+    const SAMPLES: [(u8, u8, u8); 8] = [
+        (0, 0, 0), (255, 255, 255), (128, 128, 128), (255, 0, 0),
+        (12, 200, 90), (30, 60, 240), (250, 200, 10), (170, 20, 160)
+    ];
+
+    #[test]
+    fn hex_parses_long_and_short_forms(){
+        assert_eq!(Color::from_hex("#ff8000"), Ok(Color::new(255, 128, 0)));
+        assert_eq!(Color::from_hex("FF8000"), Ok(Color::new(255, 128, 0)));
+        assert_eq!(Color::from_hex("#f80"), Ok(Color::new(255, 136, 0)));
+        assert!(Color::from_hex("#ff80").is_err());
+        assert!(Color::from_hex("#gg0000").is_err());
+        for (r, g, b) in SAMPLES{
+            let c = Color::new(r, g, b);
+            assert_eq!(Color::from_hex(&c.to_hex()), Ok(c));
+        }
+    }
+
+    #[test]
+    fn parse_accepts_names_and_functions(){
+        assert_eq!(Color::parse("rebeccapurple"), Ok(Color::new(0x66, 0x33, 0x99)));
+        assert_eq!(Color::parse(" Red "), Ok(Color::new(255, 0, 0)));
+        assert_eq!(Color::parse("#00ff00"), Ok(Color::new(0, 255, 0)));
+        assert_eq!(Color::parse("rgb(10, 20, 30)"), Ok(Color::new(10, 20, 30)));
+        assert_eq!(Color::parse("rgb(10 20 30 / 0.5)"), Ok(Color::new(10, 20, 30)));
+        assert_eq!(Color::parse("rgb(100%, 0%, 50%)"), Ok(Color::new(255, 0, 128)));
+        assert_eq!(Color::parse("hsl(120deg, 100%, 50%)"), Ok(Color::new(0, 255, 0)));
+        assert_eq!(Color::parse("hsl(240 100 50)"), Ok(Color::new(0, 0, 255)));
+        assert_eq!("blue".parse::<Color>(), Ok(Color::new(0, 0, 255)));
+        assert!(Color::parse("rgb(1, 2)").is_err());
+        assert!(Color::parse("cmyk(1, 2, 3, 4)").is_err());
+        assert!(Color::parse("notacolor").is_err());
+    }
+
+    #[test]
+    fn hsv_and_hsl_round_trip(){
+        for (r, g, b) in SAMPLES{
+            let c = Color::new(r, g, b);
+            assert_eq!(Color::from_hsv(c.to_hsv()), c);
+            assert_eq!(Color::from_hsl(c.to_hsl()), c);
+        }
+        let hsv = Color::new(255, 0, 0).to_hsv();
+        assert_eq!((hsv.h, hsv.s, hsv.v), (0.0, 1.0, 1.0));
+        let hsl = Color::new(0, 0, 255).to_hsl();
+        assert_eq!((hsl.h, hsl.s, hsl.l), (240.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn oklab_round_trips(){
+        for (r, g, b) in SAMPLES{
+            let c = Color::new(r, g, b);
+            assert_eq!(Color::from_oklab(c.to_oklab()), c);
+            assert_eq!(Color::from_oklch(c.to_oklch()), c);
+        }
+        // White sits at L 1 with no chroma
+        let white = Color::new(255, 255, 255).to_oklab();
+        assert!((white.l - 1.0).abs() < 0.001 && white.a.abs() < 0.001 && white.b.abs() < 0.001);
+    }
+
+    #[test]
+    fn srgb_transfer_round_trips(){
+        for i in 0..=255u8{
+            let c = i as f32 / 255.0;
+            assert_eq!(to_u8(linear_to_srgb(srgb_to_linear(c))), i);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use super::color::*;
-use crate::utils::temp_to_color;
+use super::position::Position;
+use crate::color_temp::{kelvin_to_color, color_to_kelvin, kelvin_to_mired};
 
 pub enum Res{
     Color(Color),
@@ -21,36 +22,128 @@ pub trait TranspT{
     fn get_transp(&self) -> u8;
 }
 
+// Whether a light that lacks a capability maps writes to it onto what it
+// has: colors on a temperature light become its nearest temperature and
+// brightness, temperatures on an rgb light become their blackbody color
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum CapabilityMapping{
+    #[default]
+    Map,
+    Ignore
+}
+
 #[derive(Clone, Copy, Default, PartialEq, Debug)]
 pub struct RgbLight{
     color: Color,
-    transparency: u8
+    transparency: u8,
+    mapping: CapabilityMapping
+}
+// Which of an RgbtLight's color and temperature is shown. In Color and White
+// the last write switches between them, Mix blends the two at a fixed ratio
+// (0 is all color, 255 all white) whatever was written last.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum RgbtMode{
+    #[default]
+    Color,
+    White,
+    Mix(u8)
 }
+// What a tunable bulb should be sent
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RgbtOutput{
+    Color(Color),
+    White(u32)
+}
+
 #[derive(Clone, Copy, Default, PartialEq, Debug)]
 pub struct RgbtLight{
     color: Color,
     temp: u32,
-    transparency: u8
+    transparency: u8,
+    mode: RgbtMode
 }
-#[derive(Clone, Copy, Default, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct TLight{
     temp: u32,
-    transparency: u8
+    brightness: u8,
+    transparency: u8,
+    mapping: CapabilityMapping
+}
+
+// How much of a color is moved onto the white channel
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum WhiteExtraction{
+    // White stays off, the color is shown with the rgb channels only
+    None,
+    // The part shared by all three channels goes to white
+    #[default]
+    Min,
+    // As Min, but measured against the color of the white led so tinted
+    // whites don't shift hue
+    WhitePoint
+}
+
+// A temperature above 0 drives the white channels, setting a color goes back
+// to the rgb channels
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct RgbwLight{
+    color: Color,
+    temp: u32,
+    transparency: u8,
+    extraction: WhiteExtraction,
+    white_temp: u32
+}
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RgbcctLight{
+    color: Color,
+    temp: u32,
+    transparency: u8,
+    warm_temp: u32,
+    cool_temp: u32
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Light{
     RGB(RgbLight),
     RGBT(RgbtLight),
-    T(TLight)
+    T(TLight),
+    RGBW(RgbwLight),
+    RGBCCT(RgbcctLight)
+}
+
+// The color scaled up until its brightest channel is full, dark colors are
+// too coarse to tell their temperature from
+fn full_brightness(color: Color) -> Color{
+    let max = color.to_bytes().into_iter().max().unwrap_or(0) as u16;
+    if max == 0{
+        return color;
+    }
+    let [r, g, b] = color.to_bytes().map(|x| (x as u16 * 255 / max) as u8);
+    return Color::new(r, g, b);
 }
 
 impl ColorT for Light {
     fn set_color(&mut self, color: Color) -> &mut Self{
         match self{
             Light::RGB(s) => s.color = color,
-            Light::RGBT(s) => s.color = color,
-            _ => ()
+            Light::RGBT(s) => {
+                s.color = color;
+                if s.mode == RgbtMode::White{
+                    s.mode = RgbtMode::Color;
+                }
+            },
+            Light::RGBW(s) => {
+                s.color = color;
+                s.temp = 0;
+            },
+            Light::RGBCCT(s) => {
+                s.color = color;
+                s.temp = 0;
+            },
+            Light::T(s) => if s.mapping == CapabilityMapping::Map{
+                s.temp = color_to_kelvin(full_brightness(color));
+                s.brightness = color.to_bytes().into_iter().max().unwrap_or(0);
+            }
         }
         return self
     }
@@ -58,6 +151,8 @@ impl ColorT for Light {
         return match self{
             Light::RGB(s) => s.color,
             Light::RGBT(s) => s.color,
+            Light::RGBW(s) => s.color,
+            Light::RGBCCT(s) => s.color,
             _ => Color::default()
         }
     }
@@ -65,9 +160,25 @@ impl ColorT for Light {
 impl TempT for Light {
     fn set_temp(&mut self, temp: u32) -> &mut Self{
         match self{
-            Light::RGBT(s) => s.temp = temp,
-            Light::T(s) => s.temp = temp,
-            _ => ()
+            // A temperature of 0 is no temperature, so it leaves the color showing
+            Light::RGBT(s) => {
+                s.temp = temp;
+                if s.mode == RgbtMode::Color && temp > 0{
+                    s.mode = RgbtMode::White;
+                }
+            },
+            // A plain temperature is shown at full brightness
+            Light::T(s) => {
+                s.temp = temp;
+                if temp > 0{
+                    s.brightness = 255;
+                }
+            },
+            Light::RGBW(s) => s.temp = temp,
+            Light::RGBCCT(s) => s.temp = temp,
+            Light::RGB(s) => if s.mapping == CapabilityMapping::Map && temp > 0{
+                s.color = kelvin_to_color(temp);
+            }
         }
         return self
     }
@@ -75,6 +186,8 @@ impl TempT for Light {
         return match self{
             Light::RGBT(s) => s.temp,
             Light::T(s) => s.temp,
+            Light::RGBW(s) => s.temp,
+            Light::RGBCCT(s) => s.temp,
             _ => 0
         }
     }
@@ -85,6 +198,8 @@ impl TranspT for Light{
             Light::RGB(s) => s.transparency = transp,
             Light::RGBT(s) => s.transparency = transp,
             Light::T(s) => s.transparency = transp,
+            Light::RGBW(s) => s.transparency = transp,
+            Light::RGBCCT(s) => s.transparency = transp
         }
         return self
     }
@@ -92,7 +207,9 @@ impl TranspT for Light{
         return match self{
             Light::RGB(s) => s.transparency,
             Light::RGBT(s) => s.transparency,
-            Light::T(s) => s.transparency
+            Light::T(s) => s.transparency,
+            Light::RGBW(s) => s.transparency,
+            Light::RGBCCT(s) => s.transparency
         }
     }
 }
@@ -106,32 +223,275 @@ impl RgbLight {
     }
 }
 impl RgbtLight {
+    pub fn get_mode(&self) -> RgbtMode{
+        return self.mode;
+    }
+    pub fn set_mode(&mut self, mode: RgbtMode){
+        self.mode = mode;
+    }
+    pub fn resolve(&self) -> RgbtOutput{
+        return match self.mode{
+            RgbtMode::White if self.temp > 0 => RgbtOutput::White(self.temp),
+            RgbtMode::Mix(ratio) if self.temp > 0 => RgbtOutput::Color(self.color.mix(&kelvin_to_color(self.temp), ratio)),
+            _ => RgbtOutput::Color(self.color)
+        };
+    }
+    pub fn get_output_color(&self) -> Color{
+        return match self.resolve(){
+            RgbtOutput::Color(x) => x,
+            RgbtOutput::White(x) => kelvin_to_color(x)
+        };
+    }
     pub fn as_string(&self) -> String{
-        return self.color.as_string();
+        return self.get_output_color().as_string();
     }
     pub fn default_enum() -> Light{
         return Light::RGBT(RgbtLight::default());
     }
 }
+impl Default for TLight{
+    fn default() -> TLight{
+        return TLight { temp: 0, brightness: 255, transparency: 0, mapping: CapabilityMapping::default() };
+    }
+}
 impl TLight {
+    pub fn get_brightness(&self) -> u8{
+        return self.brightness;
+    }
+    pub fn set_brightness(&mut self, brightness: u8){
+        self.brightness = brightness;
+    }
+    pub fn get_output_color(&self) -> Color{
+        if self.temp == 0{
+            return Color::default();
+        }
+        let b = self.brightness as u16;
+        let [r, g, bl] = kelvin_to_color(self.temp).to_bytes();
+        return Color::new((r as u16 * b / 255) as u8, (g as u16 * b / 255) as u8, (bl as u16 * b / 255) as u8);
+    }
     pub fn as_string(&self) -> String{
-        return temp_to_color(self.temp).as_string();
+        return self.get_output_color().as_string();
     }
     pub fn default_enum() -> Light{
         return Light::T(TLight::default());
     }
 }
+impl RgbwLight {
+    pub fn new(extraction: WhiteExtraction, white_temp: u32) -> RgbwLight{
+        return RgbwLight { extraction, white_temp, ..RgbwLight::default() };
+    }
+    pub fn default_enum() -> Light{
+        return Light::RGBW(RgbwLight::default());
+    }
+    pub fn get_extraction(&self) -> WhiteExtraction{
+        return self.extraction;
+    }
+    pub fn set_extraction(&mut self, extraction: WhiteExtraction){
+        self.extraction = extraction;
+    }
+    // Temperature of the white led, 0 for a neutral white
+    pub fn get_white_temp(&self) -> u32{
+        return self.white_temp;
+    }
+    pub fn set_white_temp(&mut self, temp: u32){
+        self.white_temp = temp;
+    }
+    pub fn get_output_color(&self) -> Color{
+        return if self.temp > 0 {kelvin_to_color(self.temp)} else {self.color};
+    }
+    // Red, green, blue and white channel values
+    pub fn get_channels(&self) -> [u8; 4]{
+        let color = self.get_output_color().to_bytes();
+        let white = match self.extraction{
+            WhiteExtraction::None => return [color[0], color[1], color[2], 0],
+            WhiteExtraction::Min => [255, 255, 255],
+            WhiteExtraction::WhitePoint if self.white_temp == 0 => [255, 255, 255],
+            WhiteExtraction::WhitePoint => kelvin_to_color(self.white_temp).to_bytes()
+        };
+        // The most white that fits under every channel
+        let mut w = 255.0_f32;
+        for i in 0..3{
+            if white[i] > 0{
+                w = w.min(color[i] as f32 / white[i] as f32 * 255.0);
+            }
+        }
+        let w = w.round().clamp(0.0, 255.0);
+        let mut out = [0, 0, 0, w as u8];
+        for i in 0..3{
+            out[i] = (color[i] as f32 - w * white[i] as f32 / 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        return out;
+    }
+    pub fn as_string(&self) -> String{
+        return self.get_output_color().as_string();
+    }
+}
+impl Default for RgbcctLight{
+    fn default() -> RgbcctLight{
+        return RgbcctLight { color: Color::default(), temp: 0, transparency: 0, warm_temp: 2700, cool_temp: 6500 };
+    }
+}
+impl RgbcctLight {
+    pub fn new(warm_temp: u32, cool_temp: u32) -> RgbcctLight{
+        return RgbcctLight { warm_temp, cool_temp, ..RgbcctLight::default() };
+    }
+    pub fn default_enum() -> Light{
+        return Light::RGBCCT(RgbcctLight::default());
+    }
+    pub fn get_white_temps(&self) -> (u32, u32){
+        return (self.warm_temp, self.cool_temp);
+    }
+    pub fn set_white_temps(&mut self, warm_temp: u32, cool_temp: u32){
+        self.warm_temp = warm_temp;
+        self.cool_temp = cool_temp;
+    }
+    pub fn get_output_color(&self) -> Color{
+        return if self.temp > 0 {kelvin_to_color(self.temp)} else {self.color};
+    }
+    // Red, green, blue, warm white and cool white channel values. A temperature
+    // is mixed from the two whites in mired, which is close to how it looks,
+    // while a color only has its shared white part split between them.
+    pub fn get_channels(&self) -> [u8; 5]{
+        if self.temp > 0{
+            let (warm, cool) = (kelvin_to_mired(self.warm_temp as f64), kelvin_to_mired(self.cool_temp as f64));
+            let t = if warm == cool {0.5} else {((warm - kelvin_to_mired(self.temp as f64)) / (warm - cool)).clamp(0.0, 1.0)};
+            return [0, 0, 0, (255.0 * (1.0 - t)).round() as u8, (255.0 * t).round() as u8];
+        }
+        let [r, g, b] = self.color.to_bytes();
+        let w = r.min(g).min(b);
+        return [r - w, g - w, b - w, w - w / 2, w / 2];
+    }
+    pub fn as_string(&self) -> String{
+        return self.get_output_color().as_string();
+    }
+}
 impl Light{
     pub fn clear(&mut self){
         self.set_color(Color::new(0, 0, 0));
         self.set_temp(0);
         self.set_transp(255);
     }
+    pub fn get_output_color(&self) -> Color{
+        return match self{
+            Self::RGB(x) => x.color,
+            Self::RGBT(x) => x.get_output_color(),
+            Self::T(x) => x.get_output_color(),
+            Self::RGBW(x) => x.get_output_color(),
+            Self::RGBCCT(x) => x.get_output_color()
+        }
+    }
+    // Values for each channel of the device, in its wiring order
+    pub fn get_channels(&self) -> Vec<u8>{
+        return match self{
+            Self::RGBW(x) => x.get_channels().to_vec(),
+            Self::RGBCCT(x) => x.get_channels().to_vec(),
+            _ => self.get_output_color().to_bytes().to_vec()
+        }
+    }
+    pub fn blend(&mut self, above: &Light){
+        let transp = above.get_transp();
+        if transp == 255{
+            return;
+        }
+        let below = self.get_output_color();
+        // A mostly opaque layer decides whether the light shows color or white
+        if transp < 128{
+            if let Self::RGBT(x) = self{
+                x.mode = RgbtMode::Color;
+            }
+        }
+        let mixed = above.get_output_color().mix(&below, transp);
+        self.set_color(mixed);
+        if transp < 128{
+            match (above, &mut *self){
+                (Self::RGBT(a), Self::RGBT(b)) if matches!(a.mode, RgbtMode::Mix(_)) && a.temp > 0 => {
+                    b.color = a.color.mix(&below, transp);
+                    b.temp = a.temp;
+                    b.mode = a.mode;
+                },
+                // set_temp would show the temperature at full brightness
+                (_, Self::T(b)) => if let Some(temp) = above.get_white_temp(){
+                    b.temp = temp;
+                    b.brightness = mixed.to_bytes().into_iter().max().unwrap_or(0);
+                },
+                _ => if let Some(temp) = above.get_white_temp(){
+                    if self.supports_temp(){
+                        self.set_temp(temp);
+                    }
+                }
+            }
+        }
+        self.set_transp(((self.get_transp() as u16 * transp as u16) / 255) as u8);
+    }
+    // The temperature the light is showing, None while it shows a color
+    pub fn get_white_temp(&self) -> Option<u32>{
+        return match self{
+            Self::RGB(_) => None,
+            Self::RGBT(x) => match x.resolve(){
+                RgbtOutput::White(t) => Some(t),
+                RgbtOutput::Color(_) => None
+            },
+            _ if self.get_temp() > 0 => Some(self.get_temp()),
+            _ => None
+        };
+    }
+    pub fn supports_color(&self) -> bool{
+        return !matches!(self, Self::T(_));
+    }
+    pub fn supports_temp(&self) -> bool{
+        return !matches!(self, Self::RGB(_));
+    }
+    pub fn get_mapping(&self) -> CapabilityMapping{
+        return match self{
+            Self::RGB(x) => x.mapping,
+            Self::T(x) => x.mapping,
+            _ => CapabilityMapping::default()
+        }
+    }
+    // Only lights missing a capability have anything to map
+    pub fn set_mapping(&mut self, mapping: CapabilityMapping){
+        match self{
+            Self::RGB(x) => x.mapping = mapping,
+            Self::T(x) => x.mapping = mapping,
+            _ => ()
+        }
+    }
+    // This light's state carried over to the kind of light target is. Colors
+    // become their nearest temperature and temperatures their color.
+    pub fn convert_to(&self, target: &Light) -> Light{
+        // Lights showing a temperature keep showing one where they can
+        let temp_mode = match self{
+            Self::T(_) | Self::RGBW(_) | Self::RGBCCT(_) => self.get_temp() > 0,
+            Self::RGBT(x) => matches!(x.resolve(), RgbtOutput::White(_)),
+            _ => false
+        };
+        let color = self.get_output_color();
+        let temp = if self.get_temp() > 0 {self.get_temp()} else {color_to_kelvin(full_brightness(color))};
+        let mut out = target.clone();
+        match &mut out{
+            // Written directly, set_temp would reset the brightness on a T and
+            // swap a dimmed color for the full blackbody color on an RGB
+            Self::T(x) => {
+                x.temp = temp;
+                x.brightness = color.to_bytes().into_iter().max().unwrap_or(0);
+            },
+            Self::RGB(x) => x.color = color,
+            _ => if temp_mode{
+                out.set_color(color).set_temp(temp);
+            }else{
+                out.set_temp(temp).set_color(color);
+            }
+        }
+        out.set_transp(self.get_transp());
+        return out;
+    }
     pub fn as_string(&self) -> String{
         return match self{
             Self::RGB(x) => x.as_string(),
             Self::RGBT(x) => x.as_string(),
-            Self::T(x) => x.as_string()
+            Self::T(x) => x.as_string(),
+            Self::RGBW(x) => x.as_string(),
+            Self::RGBCCT(x) => x.as_string()
         }
     }
 }
@@ -143,6 +503,13 @@ pub trait LightVec{
     fn set_name(&mut self, name: String);
     fn sync_structure(&mut self, state: &Self);
 
+    fn get_positions(&self) -> Vec<Option<Position>>{
+        return vec![None; self.size()];
+    }
+    fn get_position(&self, index: usize) -> Option<Position>{
+        return self.get_positions().get(index).cloned().flatten();
+    }
+
     fn set_color(&mut self, color: Color) -> &mut Self{
         for i in self._get_lights_mut(){
             i.set_color(color);
@@ -192,7 +559,7 @@ pub trait LightVec{
             if i.get_color() != f.get_color() {return Res::Mixed}
         }
 
-        return Res::Mixed;
+        return Res::Color(f.get_color());
     }
     fn get_temp(&self) -> Res{
         let v = self._get_lights();
@@ -205,7 +572,7 @@ pub trait LightVec{
             if i.get_temp() != f.get_temp() {return Res::Mixed}
         }
 
-        return Res::Mixed;
+        return Res::Temp(f.get_temp());
     }
     fn get_transp(&self) -> Res{
         let v = self._get_lights();
@@ -218,7 +585,7 @@ pub trait LightVec{
             if i.get_transp() != f.get_transp() {return Res::Mixed}
         }
 
-        return Res::Mixed;
+        return Res::Transparency(f.get_transp());
     }
 
     fn size(&self) -> usize{
@@ -247,3 +614,218 @@ pub trait LightVec{
         }
     }
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn cleared(light: Light) -> Light{
+        let mut out = light;
+        out.clear();
+        return out;
+    }
+    fn resolve(light: &Light) -> RgbtOutput{
+        return match light{
+            Light::RGBT(x) => x.resolve(),
+            _ => panic!("Not an rgbt light")
+        };
+    }
+
+    #[test]
+    fn color_written_after_temp_stays_color_when_blended(){
+        let mut above = RgbtLight::default_enum();
+        above.set_temp(3000).set_color(Color::new(255, 0, 0)).set_transp(0);
+        assert_eq!(resolve(&above), RgbtOutput::Color(Color::new(255, 0, 0)));
+
+        let mut below = cleared(RgbtLight::default_enum());
+        below.blend(&above);
+        assert_eq!(resolve(&below), RgbtOutput::Color(Color::new(255, 0, 0)));
+    }
+
+    #[test]
+    fn white_layers_stay_white_when_blended(){
+        let mut above = RgbtLight::default_enum();
+        above.set_color(Color::new(255, 0, 0)).set_temp(3000).set_transp(0);
+
+        let mut below = cleared(RgbtLight::default_enum());
+        below.blend(&above);
+        assert_eq!(resolve(&below), RgbtOutput::White(3000));
+
+        // The next frame's color layer takes the light back to color
+        let mut color = RgbtLight::default_enum();
+        color.set_color(Color::new(0, 0, 255)).set_transp(0);
+        below.clear();
+        below.blend(&color);
+        assert_eq!(resolve(&below), RgbtOutput::Color(Color::new(0, 0, 255)));
+    }
+
+    #[test]
+    fn mix_mode_is_carried_across(){
+        let mut above = RgbtLight::default_enum();
+        if let Light::RGBT(x) = &mut above{
+            x.set_mode(RgbtMode::Mix(128));
+        }
+        above.set_color(Color::new(255, 0, 0)).set_temp(4000).set_transp(0);
+
+        let mut below = cleared(RgbtLight::default_enum());
+        below.blend(&above);
+        assert_eq!(resolve(&below), resolve(&above));
+        assert_eq!(below.get_output_color(), above.get_output_color());
+    }
+
+    #[test]
+    fn transparent_layers_leave_the_light_alone(){
+        let mut above = RgbtLight::default_enum();
+        above.set_temp(3000);
+        above.clear();
+        let mut below = RgbtLight::default_enum();
+        below.set_color(Color::new(1, 2, 3)).set_transp(0);
+        let before = below.clone();
+        below.blend(&above);
+        assert_eq!(below, before);
+    }
+
+    fn t_light(temp: u32, brightness: u8) -> Light{
+        let mut out = TLight::default_enum();
+        out.set_temp(temp);
+        if let Light::T(x) = &mut out{
+            x.set_brightness(brightness);
+        }
+        return out;
+    }
+    fn brightness(light: &Light) -> u8{
+        return match light{
+            Light::T(x) => x.get_brightness(),
+            _ => panic!("Not a t light")
+        };
+    }
+
+    #[test]
+    fn dimmed_temperatures_keep_their_brightness_when_blended(){
+        let above = t_light(3000, 40);
+        let mut below = cleared(TLight::default_enum());
+        below.blend(&above);
+        assert_eq!(below.get_temp(), 3000);
+        assert_eq!(brightness(&below), 40);
+        assert_eq!(below.get_output_color(), above.get_output_color());
+
+        // An rgb light shows the dimmed blackbody color
+        let mut rgb = cleared(RgbLight::default_enum());
+        rgb.blend(&above);
+        assert_eq!(rgb.get_output_color(), above.get_output_color());
+    }
+
+    #[test]
+    fn colors_map_onto_temperature_lights(){
+        let mut light = TLight::default_enum();
+        light.set_color(kelvin_to_color(2700).scale(0.5));
+        assert!((light.get_temp() as i32 - 2700).abs() < 100, "{}", light.get_temp());
+        assert!(brightness(&light) < 200);
+
+        let mut ignored = TLight::default_enum();
+        ignored.set_mapping(CapabilityMapping::Ignore);
+        ignored.set_temp(4000).set_color(Color::new(255, 0, 0));
+        assert_eq!(ignored.get_temp(), 4000);
+
+        let mut rgb = RgbLight::default_enum();
+        rgb.set_temp(2700);
+        assert_eq!(rgb.get_color(), kelvin_to_color(2700));
+        rgb.set_mapping(CapabilityMapping::Ignore);
+        rgb.set_color(Color::new(1, 2, 3)).set_temp(6500);
+        assert_eq!(rgb.get_color(), Color::new(1, 2, 3));
+    }
+
+    #[test]
+    fn conversion_between_rgb_and_t_keeps_brightness(){
+        let dimmed = t_light(3000, 40);
+        let rgb = dimmed.convert_to(&RgbLight::default_enum());
+        assert_eq!(rgb.get_output_color(), dimmed.get_output_color());
+
+        let back = rgb.convert_to(&TLight::default_enum());
+        assert!((back.get_temp() as i32 - 3000).abs() < 150, "{}", back.get_temp());
+        assert_eq!(brightness(&back), 40);
+
+        let black = Light::RGB(RgbLight::default()).convert_to(&TLight::default_enum());
+        assert_eq!(black.get_temp(), 0);
+        assert_eq!(black.get_output_color(), Color::new(0, 0, 0));
+    }
+
+    fn t_rgbw(extraction: WhiteExtraction, white_temp: u32) -> Light{
+        return Light::RGBW(RgbwLight::new(extraction, white_temp));
+    }
+
+    #[test]
+    fn rgbw_extracts_the_shared_white(){
+        let mut light = t_rgbw(WhiteExtraction::None, 0);
+        light.set_color(Color::new(200, 100, 50));
+        assert_eq!(light.get_channels(), vec![200, 100, 50, 0]);
+
+        let mut light = t_rgbw(WhiteExtraction::Min, 0);
+        light.set_color(Color::new(200, 100, 50));
+        assert_eq!(light.get_channels(), vec![150, 50, 0, 50]);
+        light.set_color(Color::new(255, 255, 255));
+        assert_eq!(light.get_channels(), vec![0, 0, 0, 255]);
+        assert_eq!(light.get_output_color(), Color::new(255, 255, 255));
+
+        // A neutral white led behaves like Min
+        let mut light = t_rgbw(WhiteExtraction::WhitePoint, 0);
+        light.set_color(Color::new(200, 100, 50));
+        assert_eq!(light.get_channels(), vec![150, 50, 0, 50]);
+    }
+
+    #[test]
+    fn rgbw_white_point_keeps_the_hue(){
+        let white = kelvin_to_color(3000).to_bytes();
+        for color in [Color::new(255, 200, 150), Color::new(90, 200, 40), kelvin_to_color(3000)]{
+            let mut light = t_rgbw(WhiteExtraction::WhitePoint, 3000);
+            light.set_color(color);
+            let out = light.get_channels();
+            // The rgb channels plus the tinted white add back up to the color
+            for i in 0..3{
+                let total = out[i] as f32 + out[3] as f32 * white[i] as f32 / 255.0;
+                assert!((total - color.to_bytes()[i] as f32).abs() <= 1.0, "{:?} gave {:?}", color, out);
+            }
+            // and as much as possible went to white
+            assert!(out[3] == 255 || out[..3].iter().any(|x| *x <= 1), "{:?} gave {:?}", color, out);
+        }
+        let mut light = t_rgbw(WhiteExtraction::WhitePoint, 3000);
+        light.set_temp(3000);
+        assert_eq!(light.get_channels(), vec![0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rgbcct_mixes_temperatures_from_both_whites(){
+        let mut light = Light::RGBCCT(RgbcctLight::new(2700, 6500));
+        light.set_temp(2700);
+        assert_eq!(light.get_channels(), vec![0, 0, 0, 255, 0]);
+        light.set_temp(6500);
+        assert_eq!(light.get_channels(), vec![0, 0, 0, 0, 255]);
+        // Outside the range the nearest white is used alone
+        light.set_temp(1800);
+        assert_eq!(light.get_channels(), vec![0, 0, 0, 255, 0]);
+        light.set_temp(10000);
+        assert_eq!(light.get_channels(), vec![0, 0, 0, 0, 255]);
+
+        // Halfway in mired, not in kelvin
+        light.set_temp(3815);
+        let out = light.get_channels();
+        assert!(out[3].abs_diff(128) <= 1 && out[4].abs_diff(127) <= 1, "{:?}", out);
+        assert_eq!(out[3] as u16 + out[4] as u16, 255);
+        light.set_temp(4600);
+        assert!(light.get_channels()[3] < light.get_channels()[4]);
+
+        let mut light = Light::RGBCCT(RgbcctLight::new(3000, 3000));
+        light.set_temp(4000);
+        assert_eq!(light.get_channels(), vec![0, 0, 0, 128, 128]);
+    }
+
+    #[test]
+    fn rgbcct_splits_the_white_of_a_color(){
+        let mut light = Light::RGBCCT(RgbcctLight::default());
+        light.set_temp(3000);
+        light.set_color(Color::new(200, 100, 51));
+        assert_eq!(light.get_channels(), vec![149, 49, 0, 26, 25]);
+        assert_eq!(light.get_output_color(), Color::new(200, 100, 51));
+        assert_eq!(RgbLight::default_enum().get_channels().len(), 3);
+    }
+}
@@ -1,4 +1,6 @@
+use super::color::Color;
 use super::light_primitive::*;
+use super::position::*;
 use crate::utils::*;
 
 #[derive(Debug, Clone)]
@@ -7,13 +9,25 @@ pub struct LightStrip{
     light_type: Light,
     pin:u8,
     length: usize,
-    name: String
+    name: String,
+    start_channel: Option<u32>,
+    positions: Option<Vec<Position>>
+}
+#[derive(Debug, Clone)]
+pub struct LightMatrix{
+    lights: Vec<Light>,
+    light_type: Light,
+    pin: u8,
+    layout: MatrixLayout,
+    name: String,
+    start_channel: Option<u32>
 }
 #[derive(Debug, Clone)]
 pub struct Bulb{
     light: Light,
     ip: String,
-    name: String
+    name: String,
+    position: Option<Position>
 }
 #[derive(Debug, Clone)]
 pub struct BulbGroup{
@@ -22,6 +36,89 @@ pub struct BulbGroup{
     name: String
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WiringOrder{
+    Rows,
+    Columns
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartCorner{
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight
+}
+
+// How the pixels of a panel are wired, and how panels are tiled. Panels are
+// chained row by row, reversing every other row of panels when tile_serpentine
+// is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatrixLayout{
+    pub panel_width: usize,
+    pub panel_height: usize,
+    pub order: WiringOrder,
+    pub serpentine: bool,
+    pub start_corner: StartCorner,
+    pub tiles_x: usize,
+    pub tiles_y: usize,
+    pub tile_serpentine: bool
+}
+
+impl MatrixLayout{
+    pub fn new(width: usize, height: usize) -> MatrixLayout{
+        return MatrixLayout {
+            panel_width: width,
+            panel_height: height,
+            order: WiringOrder::Rows,
+            serpentine: true,
+            start_corner: StartCorner::TopLeft,
+            tiles_x: 1,
+            tiles_y: 1,
+            tile_serpentine: false
+        };
+    }
+    pub fn get_width(&self) -> usize{
+        return self.panel_width * self.tiles_x;
+    }
+    pub fn get_height(&self) -> usize{
+        return self.panel_height * self.tiles_y;
+    }
+    pub fn size(&self) -> usize{
+        return self.get_width() * self.get_height();
+    }
+
+    // Position of the pixel at (x, y) along the wiring, (0, 0) is the top left
+    pub fn xy_to_index(&self, x: usize, y: usize) -> Option<usize>{
+        if x >= self.get_width() || y >= self.get_height(){
+            return None;
+        }
+        let (pw, ph) = (self.panel_width, self.panel_height);
+        let (tile_x, tile_y) = (x / pw, y / ph);
+        let tile_x = if self.tile_serpentine && tile_y % 2 == 1 {self.tiles_x - 1 - tile_x} else {tile_x};
+        let tile = tile_y * self.tiles_x + tile_x;
+
+        let mut lx = x % pw;
+        let mut ly = y % ph;
+        if let StartCorner::TopRight | StartCorner::BottomRight = self.start_corner{
+            lx = pw - 1 - lx;
+        }
+        if let StartCorner::BottomLeft | StartCorner::BottomRight = self.start_corner{
+            ly = ph - 1 - ly;
+        }
+        let local = match self.order{
+            WiringOrder::Rows => ly * pw + if self.serpentine && ly % 2 == 1 {pw - 1 - lx} else {lx},
+            WiringOrder::Columns => lx * ph + if self.serpentine && lx % 2 == 1 {ph - 1 - ly} else {ly}
+        };
+        return Some(tile * pw * ph + local);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum GroupCommand{
+    Broadcast(Light),
+    Unicast(Vec<(String, Light)>)
+}
+
 impl LightStrip{
     pub fn new(name: String, pin: u8, length: usize, type_: Light) -> LightStrip{
         let mut base = type_.clone();
@@ -30,7 +127,7 @@ impl LightStrip{
         for _ in 0..length{
             v.push(base.clone());
         }
-        return LightStrip {name: name, lights: v, light_type:base, pin: pin, length: length}
+        return LightStrip {name: name, lights: v, light_type:base, pin: pin, length: length, start_channel: None, positions: None}
     }
     pub fn new_enum(name: String, pin: u8, length: usize, type_: Light) -> LightingTypes{
         return LightingTypes::LightStrip(LightStrip::new(name, pin, length, type_));
@@ -45,6 +142,21 @@ impl LightStrip{
     pub fn get_length(&self) -> usize{
         return self.length;
     }
+    pub fn get_light_type(&self) -> &Light{
+        return &self.light_type;
+    }
+    pub fn set_light_type(&mut self, type_: Light){
+        let mut base = type_.clone();
+        base.clear();
+        self.lights = self.lights.iter().map(|x| x.convert_to(&base)).collect();
+        self.light_type = base;
+    }
+    pub fn set_mapping(&mut self, mapping: CapabilityMapping){
+        self.light_type.set_mapping(mapping);
+        for i in &mut self.lights{
+            i.set_mapping(mapping);
+        }
+    }
     pub fn set_length(&mut self, length: usize){
         self.length = length;
         let diff = (length as isize) - (self.lights.len() as isize);
@@ -58,11 +170,124 @@ impl LightStrip{
                 self.lights.pop();
             }
         }
+        if let Some(p) = &mut self.positions{
+            let last = p.last().cloned().unwrap_or_default();
+            p.resize(length, last);
+        }
+    }
+    pub fn get_start_channel(&self) -> Option<u32>{
+        return self.start_channel;
+    }
+    pub fn set_start_channel(&mut self, channel: Option<u32>){
+        self.start_channel = channel;
+    }
+    pub fn get_position_list(&self) -> Option<&Vec<Position>>{
+        return self.positions.as_ref();
+    }
+    // Spread positions evenly from the first light to the last
+    pub fn set_positions_line(&mut self, start: Position, end: Position){
+        let mut out: Vec<Position> = Vec::with_capacity(self.length);
+        for i in 0..self.length{
+            let t = if self.length > 1 {i as f32 / (self.length - 1) as f32} else {0.0};
+            out.push(start.lerp(&end, t));
+        }
+        self.positions = Some(out);
+    }
+    pub fn set_position(&mut self, index: usize, position: Position){
+        if index >= self.length{
+            return;
+        }
+        let positions = self.positions.get_or_insert(vec![Position::default(); self.length]);
+        positions[index] = position;
+    }
+    pub fn set_positions(&mut self, positions: Option<Vec<Position>>){
+        self.positions = match positions{
+            Some(mut x) => {
+                let last = x.last().cloned().unwrap_or_default();
+                x.resize(self.length, last);
+                Some(x)
+            },
+            None => None
+        };
+    }
+}
+impl LightMatrix{
+    pub fn new(name: String, pin: u8, layout: MatrixLayout, type_: Light) -> LightMatrix{
+        let mut base = type_.clone();
+        base.clear();
+        let lights = vec![base.clone(); layout.size()];
+        return LightMatrix {name: name, lights: lights, light_type: base, pin: pin, layout: layout, start_channel: None}
+    }
+    pub fn new_enum(name: String, pin: u8, layout: MatrixLayout, type_: Light) -> LightingTypes{
+        return LightingTypes::LightMatrix(LightMatrix::new(name, pin, layout, type_));
+    }
+
+    pub fn get_pin(&self) -> u8{
+        return self.pin;
+    }
+    pub fn set_pin(&mut self, pin: u8){
+        self.pin = pin;
+    }
+    pub fn get_layout(&self) -> MatrixLayout{
+        return self.layout;
+    }
+    pub fn get_start_channel(&self) -> Option<u32>{
+        return self.start_channel;
+    }
+    pub fn set_start_channel(&mut self, channel: Option<u32>){
+        self.start_channel = channel;
+    }
+    pub fn get_light_type(&self) -> &Light{
+        return &self.light_type;
+    }
+    pub fn set_light_type(&mut self, type_: Light){
+        let mut base = type_.clone();
+        base.clear();
+        self.lights = self.lights.iter().map(|x| x.convert_to(&base)).collect();
+        self.light_type = base;
+    }
+    pub fn set_mapping(&mut self, mapping: CapabilityMapping){
+        self.light_type.set_mapping(mapping);
+        for i in &mut self.lights{
+            i.set_mapping(mapping);
+        }
+    }
+    pub fn set_layout(&mut self, layout: MatrixLayout){
+        self.layout = layout;
+        self.lights.resize(layout.size(), self.light_type.clone());
+    }
+    pub fn get_width(&self) -> usize{
+        return self.layout.get_width();
+    }
+    pub fn get_height(&self) -> usize{
+        return self.layout.get_height();
+    }
+
+    pub fn get_light_xy(&self, x: usize, y: usize) -> Option<&Light>{
+        return self.lights.get(self.layout.xy_to_index(x, y)?);
+    }
+    pub fn set_color_xy(&mut self, x: usize, y: usize, color: Color) -> &mut Self{
+        if let Some(i) = self.layout.xy_to_index(x, y){
+            self.lights[i].set_color(color);
+        }
+        return self;
+    }
+    pub fn set_temp_xy(&mut self, x: usize, y: usize, temp: u32) -> &mut Self{
+        if let Some(i) = self.layout.xy_to_index(x, y){
+            self.lights[i].set_temp(temp);
+        }
+        return self;
+    }
+    pub fn set_transp_xy(&mut self, x: usize, y: usize, transp: u8) -> &mut Self{
+        if let Some(i) = self.layout.xy_to_index(x, y){
+            self.lights[i].set_transp(transp);
+        }
+        return self;
     }
 }
 impl Bulb{
     pub fn new(ip: String, name: String) -> Bulb{
-        return Bulb {light: Light::RGBT(RgbtLight::default()), ip: ip, name: name}
+        return Bulb {light: Light::RGBT(RgbtLight::default()), ip: ip, name: name, position: None}
     }
     pub fn new_enum(ip: String, name: String) -> LightingTypes{
         return LightingTypes::Bulb(Bulb::new(name, ip));
@@ -73,6 +298,12 @@ impl Bulb{
     pub fn set_ip(&mut self, ip: String){
         self.ip = ip;
     }
+    pub fn set_position(&mut self, position: Option<Position>){
+        self.position = position;
+    }
+    pub fn set_light_type(&mut self, type_: Light){
+        self.light = self.light.convert_to(&type_);
+    }
 }
 impl BulbGroup{
     pub fn new(name: String) -> BulbGroup{
@@ -98,6 +329,32 @@ impl BulbGroup{
     pub fn get_bulb_mut(&mut self, index: usize) -> &mut Bulb{
         return &mut self.bulbs[index];
     }
+    pub fn get_ips(&self) -> Vec<String>{
+        return self.bulbs.iter().map(|x| x.get_ip()).collect();
+    }
+    pub fn set_light_type(&mut self, type_: Light){
+        for i in &mut self.bulbs{
+            i.set_light_type(type_.clone());
+        }
+    }
+
+    // Every bulb in exactly the same state, so one broadcast shows the same
+    pub fn is_uniform(&self) -> bool{
+        return match self.bulbs.first(){
+            None => false,
+            Some(first) => self.bulbs.iter().all(|b| b.light == first.light)
+        };
+    }
+
+    // One group/broadcast command when every bulb agrees, otherwise one per bulb
+    pub fn get_command(&self) -> GroupCommand{
+        if self.is_uniform(){
+            return GroupCommand::Broadcast(self.bulbs[0].light.clone());
+        }
+        return GroupCommand::Unicast(
+            self.bulbs.iter().map(|x| (x.get_ip(), x.light.clone())).collect()
+        );
+    }
 }
 
 
@@ -122,9 +379,68 @@ impl LightVec for LightStrip{
         if self.pin != state.get_pin(){
             self.set_pin(state.get_pin());
         }
+        if self.light_type != state.light_type{
+            self.set_light_type(state.light_type.clone());
+        }
         if self.length != state.get_length(){
             self.set_length(state.get_length());
         }
+        if self.start_channel != state.get_start_channel(){
+            self.set_start_channel(state.get_start_channel());
+        }
+        if self.positions.as_ref() != state.positions.as_ref(){
+            self.set_positions(state.positions.clone());
+        }
+    }
+    fn get_positions(&self) -> Vec<Option<Position>>{
+        return match &self.positions{
+            None => vec![None; self.length],
+            Some(x) => x.iter().map(|p| Some(*p)).collect()
+        };
+    }
+}
+impl LightVec for LightMatrix{
+    fn _get_lights_mut(&mut self) -> Vec<&mut Light> {
+        return mut_vec_to_vec_mut(&mut self.lights);
+    }
+    fn _get_lights(&self) -> Vec<&Light> {
+        return ref_vec_to_vec_ref(&self.lights);
+    }
+    fn get_name(&self) -> String{
+        return self.name.clone();
+    }
+    fn set_name(&mut self, name: String){
+        self.name = name;
+    }
+    fn sync_structure(&mut self, state: &Self) {
+        if self.name != state.get_name(){
+            self.set_name(state.get_name());
+        }
+        if self.pin != state.get_pin(){
+            self.set_pin(state.get_pin());
+        }
+        if self.light_type != state.light_type{
+            self.set_light_type(state.light_type.clone());
+        }
+        if self.layout != state.get_layout(){
+            self.set_layout(state.get_layout());
+        }
+        if self.start_channel != state.get_start_channel(){
+            self.set_start_channel(state.get_start_channel());
+        }
+    }
+    // Pixels sit on a unit grid with y pointing up, so row 0 is the top
+    fn get_positions(&self) -> Vec<Option<Position>>{
+        let mut out: Vec<Option<Position>> = vec![None; self.lights.len()];
+        let height = self.get_height();
+        for y in 0..height{
+            for x in 0..self.get_width(){
+                if let Some(i) = self.layout.xy_to_index(x, y){
+                    out[i] = Some(Position::new(x as f32, (height - 1 - y) as f32, 0.0));
+                }
+            }
+        }
+        return out;
     }
 }
 impl LightVec for Bulb{
@@ -147,6 +463,18 @@ impl LightVec for Bulb{
         if self.ip != state.get_ip(){
             self.set_ip(state.get_ip());
         }
+        if self.position != state.position{
+            self.set_position(state.position);
+        }
+        if std::mem::discriminant(&self.light) != std::mem::discriminant(&state.light){
+            self.set_light_type(state.light.clone());
+        }
+        if self.light.get_mapping() != state.light.get_mapping(){
+            self.light.set_mapping(state.light.get_mapping());
+        }
+    }
+    fn get_positions(&self) -> Vec<Option<Position>>{
+        return vec![self.position];
     }
 }
 impl LightVec for BulbGroup{
@@ -172,24 +500,79 @@ impl LightVec for BulbGroup{
     fn set_name(&mut self, name: String){
         self.name = name;
     }
+    // Bulbs are matched by ip so they keep their state when the group is reordered
     fn sync_structure(&mut self, state: &Self) {
         if self.name != state.get_name(){
             self.set_name(state.get_name());
         }
+        let mut old: Vec<Bulb> = self.bulbs.drain(..).collect();
+        for i in &state.bulbs{
+            match old.iter().position(|x| x.ip == i.ip){
+                Some(index) => {
+                    let mut bulb = old.remove(index);
+                    bulb.sync_structure(i);
+                    self.bulbs.push(bulb);
+                },
+                None => {
+                    let mut bulb = i.clone();
+                    bulb.light.clear();
+                    self.bulbs.push(bulb);
+                }
+            }
+        }
+        self.length = self.bulbs.len();
+    }
+    fn get_positions(&self) -> Vec<Option<Position>>{
+        let mut out: Vec<Option<Position>> = Vec::new();
+        for i in &self.bulbs{
+            out.append(&mut i.get_positions());
+        }
+        return out;
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum LightingTypes{
     LightStrip(LightStrip),
+    LightMatrix(LightMatrix),
     Bulb(Bulb),
     BulbGroup(BulbGroup)
 }
 
+impl LightingTypes{
+    // Converts every pixel, groups convert each of their bulbs
+    pub fn set_light_type(&mut self, type_: Light){
+        match self{
+            LightingTypes::LightStrip(x) => x.set_light_type(type_),
+            LightingTypes::LightMatrix(x) => x.set_light_type(type_),
+            LightingTypes::BulbGroup(x) => x.set_light_type(type_),
+            LightingTypes::Bulb(x) => x.set_light_type(type_)
+        }
+    }
+    // First output channel of strips and matrices wired to a fixed spot
+    pub fn get_start_channel(&self) -> Option<u32>{
+        return match self{
+            LightingTypes::LightStrip(x) => x.get_start_channel(),
+            LightingTypes::LightMatrix(x) => x.get_start_channel(),
+            _ => None
+        };
+    }
+    pub fn set_mapping(&mut self, mapping: CapabilityMapping){
+        match self{
+            LightingTypes::LightStrip(x) => x.set_mapping(mapping),
+            LightingTypes::LightMatrix(x) => x.set_mapping(mapping),
+            _ => for i in self._get_lights_mut(){
+                i.set_mapping(mapping);
+            }
+        }
+    }
+}
+
 impl LightVec for LightingTypes{
     fn _get_lights_mut(&mut self) -> Vec<&mut Light>{
         return match self{
             LightingTypes::LightStrip(x) => x._get_lights_mut(),
+            LightingTypes::LightMatrix(x) => x._get_lights_mut(),
             LightingTypes::BulbGroup(x) => x._get_lights_mut(),
             LightingTypes::Bulb(x) => x._get_lights_mut()
         }
@@ -197,6 +580,7 @@ impl LightVec for LightingTypes{
     fn _get_lights(&self) -> Vec<&Light> {
         return match self{
             LightingTypes::LightStrip(x) => x._get_lights(),
+            LightingTypes::LightMatrix(x) => x._get_lights(),
             LightingTypes::BulbGroup(x) => x._get_lights(),
             LightingTypes::Bulb(x) => x._get_lights()
         }
@@ -204,6 +588,7 @@ impl LightVec for LightingTypes{
     fn get_name(&self) -> String{
         return match self{
             LightingTypes::LightStrip(x) => x.get_name(),
+            LightingTypes::LightMatrix(x) => x.get_name(),
             LightingTypes::BulbGroup(x) => x.get_name(),
             LightingTypes::Bulb(x) => x.get_name()
         }
@@ -211,16 +596,129 @@ impl LightVec for LightingTypes{
     fn set_name(&mut self, name: String){
         match self{
             LightingTypes::LightStrip(x) => x.set_name(name),
+            LightingTypes::LightMatrix(x) => x.set_name(name),
             LightingTypes::BulbGroup(x) => x.set_name(name),
             LightingTypes::Bulb(x) => x.set_name(name)
         }
     }
+    fn get_positions(&self) -> Vec<Option<Position>>{
+        return match self{
+            LightingTypes::LightStrip(x) => x.get_positions(),
+            LightingTypes::LightMatrix(x) => x.get_positions(),
+            LightingTypes::BulbGroup(x) => x.get_positions(),
+            LightingTypes::Bulb(x) => x.get_positions()
+        }
+    }
     fn sync_structure(&mut self, state: &Self) {
         match (self, state){
             (LightingTypes::LightStrip(x), LightingTypes::LightStrip(y)) => x.sync_structure(y),
+            (LightingTypes::LightMatrix(x), LightingTypes::LightMatrix(y)) => x.sync_structure(y),
             (LightingTypes::BulbGroup(x), LightingTypes::BulbGroup(y)) => x.sync_structure(y),
             (LightingTypes::Bulb(x), LightingTypes::Bulb(y)) => x.sync_structure(y),
             _ => ()
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn t_bulb(ip: &str, temp: u32, brightness: u8) -> Bulb{
+        let mut light = TLight::default_enum();
+        light.set_temp(temp);
+        if let Light::T(x) = &mut light{
+            x.set_brightness(brightness);
+        }
+        let mut out = Bulb::new(ip.to_string(), ip.to_string());
+        out.light = light;
+        return out;
+    }
+
+    // Index of every pixel, row by row from the top left
+    fn t_grid(layout: &MatrixLayout) -> Vec<Vec<usize>>{
+        return (0..layout.get_height())
+            .map(|y| (0..layout.get_width()).map(|x| layout.xy_to_index(x, y).unwrap()).collect())
+            .collect();
+    }
+    fn t_layout(width: usize, height: usize, order: WiringOrder, serpentine: bool, start_corner: StartCorner) -> MatrixLayout{
+        let mut out = MatrixLayout::new(width, height);
+        out.order = order;
+        out.serpentine = serpentine;
+        out.start_corner = start_corner;
+        return out;
+    }
+
+    #[test]
+    fn matrix_start_corners(){
+        let grid = |corner| t_grid(&t_layout(3, 2, WiringOrder::Rows, true, corner));
+        assert_eq!(grid(StartCorner::TopLeft), vec![vec![0, 1, 2], vec![5, 4, 3]]);
+        assert_eq!(grid(StartCorner::TopRight), vec![vec![2, 1, 0], vec![3, 4, 5]]);
+        assert_eq!(grid(StartCorner::BottomLeft), vec![vec![5, 4, 3], vec![0, 1, 2]]);
+        assert_eq!(grid(StartCorner::BottomRight), vec![vec![3, 4, 5], vec![2, 1, 0]]);
+    }
+
+    #[test]
+    fn matrix_serpentine_and_columns(){
+        let rows = t_layout(3, 2, WiringOrder::Rows, false, StartCorner::TopLeft);
+        assert_eq!(t_grid(&rows), vec![vec![0, 1, 2], vec![3, 4, 5]]);
+        let columns = t_layout(3, 2, WiringOrder::Columns, false, StartCorner::TopLeft);
+        assert_eq!(t_grid(&columns), vec![vec![0, 2, 4], vec![1, 3, 5]]);
+        let columns = t_layout(3, 2, WiringOrder::Columns, true, StartCorner::TopLeft);
+        assert_eq!(t_grid(&columns), vec![vec![0, 3, 4], vec![1, 2, 5]]);
+        let columns = t_layout(3, 2, WiringOrder::Columns, true, StartCorner::BottomRight);
+        assert_eq!(t_grid(&columns), vec![vec![5, 2, 1], vec![4, 3, 0]]);
+    }
+
+    #[test]
+    fn matrix_tiles(){
+        let mut layout = t_layout(2, 2, WiringOrder::Rows, false, StartCorner::TopLeft);
+        layout.tiles_x = 2;
+        layout.tiles_y = 2;
+        assert_eq!(layout.size(), 16);
+        assert_eq!(t_grid(&layout), vec![
+            vec![0, 1, 4, 5],
+            vec![2, 3, 6, 7],
+            vec![8, 9, 12, 13],
+            vec![10, 11, 14, 15]
+        ]);
+        layout.tile_serpentine = true;
+        assert_eq!(t_grid(&layout), vec![
+            vec![0, 1, 4, 5],
+            vec![2, 3, 6, 7],
+            vec![12, 13, 8, 9],
+            vec![14, 15, 10, 11]
+        ]);
+        assert_eq!(layout.xy_to_index(4, 0), None);
+        assert_eq!(layout.xy_to_index(0, 4), None);
+    }
+
+    #[test]
+    fn matrix_indices_are_unique(){
+        for order in [WiringOrder::Rows, WiringOrder::Columns]{
+            for corner in [StartCorner::TopLeft, StartCorner::TopRight, StartCorner::BottomLeft, StartCorner::BottomRight]{
+                for serpentine in [false, true]{
+                    let mut layout = t_layout(4, 3, order, serpentine, corner);
+                    layout.tiles_x = 3;
+                    layout.tiles_y = 2;
+                    layout.tile_serpentine = serpentine;
+                    let mut all: Vec<usize> = t_grid(&layout).into_iter().flatten().collect();
+                    all.sort();
+                    assert_eq!(all, (0..layout.size()).collect::<Vec<usize>>());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn groups_differing_only_in_brightness_are_not_uniform(){
+        let mut group = BulbGroup::new("group".to_string());
+        group.add_bulb(t_bulb("10.0.0.1", 3000, 100));
+        group.add_bulb(t_bulb("10.0.0.2", 3000, 255));
+        assert!(!group.is_uniform());
+        assert!(matches!(group.get_command(), GroupCommand::Unicast(_)));
+
+        group.get_bulb_mut(0).light = group.get_bulb(1).light.clone();
+        assert!(group.is_uniform());
+        assert!(matches!(group.get_command(), GroupCommand::Broadcast(_)));
+    }
+}
@@ -1,4 +1,9 @@
 pub mod color;
+pub mod palette;
 pub mod light_primitive;
 pub mod light_types;
-pub mod profile;
\ No newline at end of file
+pub mod profile;
+pub mod position;
+pub mod segment;
+pub mod assignment;
+pub mod selector;
\ No newline at end of file
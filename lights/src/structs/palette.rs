@@ -0,0 +1,280 @@
+use super::color::{Color, ColorSpace};
+
+// What happens to positions outside 0 to 1. Wrap also blends the last stop
+// back into the first so cycling through a palette has no seam.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum EdgeMode{
+    #[default]
+    Clamp,
+    Wrap,
+    Mirror
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientStop{
+    pub position: f32,
+    pub color: Color
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient{
+    stops: Vec<GradientStop>,
+    space: ColorSpace,
+    edge: EdgeMode
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette{
+    name: String,
+    gradient: Gradient
+}
+
+impl Gradient{
+    pub fn new(space: ColorSpace, edge: EdgeMode) -> Gradient{
+        return Gradient { stops: Vec::new(), space, edge };
+    }
+    // Colors spread evenly, from 0 to 1 when clamped, leaving room for the
+    // way back to the first color when wrapped
+    pub fn from_colors(colors: &[Color], space: ColorSpace, edge: EdgeMode) -> Gradient{
+        let mut out = Gradient::new(space, edge);
+        let steps = match edge{
+            EdgeMode::Wrap => colors.len(),
+            _ => colors.len().saturating_sub(1).max(1)
+        };
+        for (index, i) in colors.iter().enumerate(){
+            out.add_stop(index as f32 / steps as f32, *i);
+        }
+        return out;
+    }
+    pub fn add_stop(&mut self, position: f32, color: Color) -> &mut Self{
+        let position = position.clamp(0.0, 1.0);
+        let index = self.stops.iter().position(|x| x.position > position).unwrap_or(self.stops.len());
+        self.stops.insert(index, GradientStop { position, color });
+        return self;
+    }
+    pub fn get_stops(&self) -> &Vec<GradientStop>{
+        return &self.stops;
+    }
+    pub fn get_space(&self) -> ColorSpace{
+        return self.space;
+    }
+    pub fn set_space(&mut self, space: ColorSpace){
+        self.space = space;
+    }
+    pub fn get_edge(&self) -> EdgeMode{
+        return self.edge;
+    }
+    pub fn set_edge(&mut self, edge: EdgeMode){
+        self.edge = edge;
+    }
+
+    fn blend(&self, a: &GradientStop, a_pos: f32, b: &GradientStop, b_pos: f32, t: f32) -> Color{
+        if b_pos <= a_pos{
+            return b.color;
+        }
+        return a.color.lerp(&b.color, (t - a_pos) / (b_pos - a_pos), self.space);
+    }
+
+    pub fn sample(&self, t: f32) -> Color{
+        let (first, last) = match (self.stops.first(), self.stops.last()){
+            (Some(a), Some(b)) => (a, b),
+            _ => return Color::default()
+        };
+        let t = match self.edge{
+            EdgeMode::Clamp => t.clamp(0.0, 1.0),
+            EdgeMode::Wrap => t.rem_euclid(1.0),
+            EdgeMode::Mirror => {
+                let m = t.rem_euclid(2.0);
+                if m > 1.0 {2.0 - m} else {m}
+            }
+        };
+        if t < first.position{
+            return match self.edge{
+                EdgeMode::Wrap => self.blend(last, last.position - 1.0, first, first.position, t),
+                _ => first.color
+            };
+        }
+        if t >= last.position{
+            return match self.edge{
+                EdgeMode::Wrap => self.blend(last, last.position, first, first.position + 1.0, t),
+                _ => last.color
+            };
+        }
+        for pair in self.stops.windows(2){
+            if t < pair[1].position{
+                return self.blend(&pair[0], pair[0].position, &pair[1], pair[1].position, t);
+            }
+        }
+        return last.color;
+    }
+    // count colors evenly spaced over the gradient, one per light of a strip
+    pub fn sample_n(&self, count: usize) -> Vec<Color>{
+        let steps = match self.edge{
+            EdgeMode::Wrap => count,
+            _ => count.saturating_sub(1).max(1)
+        };
+        return (0..count).map(|i| self.sample(i as f32 / steps as f32)).collect();
+    }
+}
+
+pub const BUILTIN_PALETTES: [&str; 5] = ["rainbow", "fire", "ocean", "party", "heat"];
+
+impl Palette{
+    pub fn new(name: String, gradient: Gradient) -> Palette{
+        return Palette { name, gradient };
+    }
+    pub fn get_name(&self) -> String{
+        return self.name.clone();
+    }
+    pub fn get_gradient(&self) -> &Gradient{
+        return &self.gradient;
+    }
+    pub fn get_gradient_mut(&mut self) -> &mut Gradient{
+        return &mut self.gradient;
+    }
+    pub fn sample(&self, t: f32) -> Color{
+        return self.gradient.sample(t);
+    }
+    pub fn sample_n(&self, count: usize) -> Vec<Color>{
+        return self.gradient.sample_n(count);
+    }
+
+    pub fn builtin(name: &str) -> Option<Palette>{
+        let c = Color::new;
+        let gradient = match name{
+            "rainbow" => Gradient::from_colors(&[
+                c(255, 0, 0), c(255, 255, 0), c(0, 255, 0), c(0, 255, 255), c(0, 0, 255), c(255, 0, 255)
+            ], ColorSpace::Hsv, EdgeMode::Wrap),
+            "fire" => Gradient::from_colors(&[
+                c(0, 0, 0), c(128, 0, 0), c(255, 48, 0), c(255, 160, 0), c(255, 240, 160)
+            ], ColorSpace::Oklab, EdgeMode::Clamp),
+            "ocean" => Gradient::from_colors(&[
+                c(0, 0, 64), c(0, 48, 160), c(0, 128, 192), c(0, 200, 200), c(160, 255, 240)
+            ], ColorSpace::Oklab, EdgeMode::Wrap),
+            "party" => Gradient::from_colors(&[
+                c(85, 0, 171), c(181, 0, 75), c(232, 23, 0), c(255, 160, 0), c(255, 0, 128), c(64, 0, 255)
+            ], ColorSpace::Oklch, EdgeMode::Wrap),
+            "heat" => Gradient::from_colors(&[
+                c(0, 0, 128), c(0, 0, 255), c(0, 255, 255), c(0, 255, 0), c(255, 255, 0), c(255, 0, 0)
+            ], ColorSpace::Srgb, EdgeMode::Clamp),
+            _ => return None
+        };
+        return Some(Palette::new(name.to_string(), gradient));
+    }
+    // A builtin name, or colors separated by commas such as "navy, #ff8000, rgb(255 255 255)"
+    pub fn parse(text: &str) -> Result<Palette, String>{
+        let text = text.trim();
+        if let Some(x) = Palette::builtin(&text.to_ascii_lowercase()){
+            return Ok(x);
+        }
+        let mut colors: Vec<Color> = Vec::new();
+        let (mut depth, mut start) = (0, 0);
+        for (index, c) in text.char_indices().chain([(text.len(), ',')]){
+            match c{
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    colors.push(Color::parse(&text[start..index])?);
+                    start = index + 1;
+                },
+                _ => ()
+            }
+        }
+        if colors.len() < 2{
+            return Err(format!("Unknown palette \"{}\", expected one of {} or a list of colors", text, BUILTIN_PALETTES.join(", ")));
+        }
+        return Ok(Palette::new(text.to_string(), Gradient::from_colors(&colors, ColorSpace::Oklab, EdgeMode::Clamp)));
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn t_primaries() -> [Color; 3]{
+        return [Color::new(255, 0, 0), Color::new(0, 255, 0), Color::new(0, 0, 255)];
+    }
+
+    #[test]
+    fn clamped_gradients_hold_their_ends(){
+        let [red, _, blue] = t_primaries();
+        let g = Gradient::from_colors(&[red, blue], ColorSpace::Srgb, EdgeMode::Clamp);
+        assert_eq!((g.sample(0.0), g.sample(1.0)), (red, blue));
+        assert_eq!((g.sample(-3.0), g.sample(7.5)), (red, blue));
+        assert_eq!(g.sample(0.5), red.lerp(&blue, 0.5, ColorSpace::Srgb));
+        assert_eq!(g.sample(0.25), red.lerp(&blue, 0.25, ColorSpace::Srgb));
+    }
+
+    #[test]
+    fn wrapped_gradients_have_no_seam(){
+        let [red, green, blue] = t_primaries();
+        let g = Gradient::from_colors(&[red, green, blue], ColorSpace::Srgb, EdgeMode::Wrap);
+        assert_eq!(g.get_stops().iter().map(|x| x.position).collect::<Vec<f32>>(), vec![0.0, 1.0 / 3.0, 2.0 / 3.0]);
+        // Past the last stop the gradient heads back to the first
+        let seam = g.sample(5.0 / 6.0).to_bytes();
+        assert!(seam[0].abs_diff(128) <= 1 && seam[1] == 0 && seam[2].abs_diff(128) <= 1, "{:?}", seam);
+        assert_eq!(g.sample(1.0), red);
+        assert_eq!(g.sample(-1.0 / 6.0), g.sample(5.0 / 6.0));
+        assert_eq!(g.sample(2.5), g.sample(0.5));
+        let [r, _, b] = g.sample(0.999).to_bytes();
+        assert!(r >= 250 && b <= 5);
+    }
+
+    #[test]
+    fn mirrored_gradients_bounce(){
+        let [red, green, blue] = t_primaries();
+        let g = Gradient::from_colors(&[red, green, blue], ColorSpace::Srgb, EdgeMode::Mirror);
+        assert_eq!(g.sample(1.0), blue);
+        assert_eq!(g.sample(1.25), g.sample(0.75));
+        assert_eq!(g.sample(2.0), red);
+        assert_eq!(g.sample(-0.25), g.sample(0.25));
+    }
+
+    #[test]
+    fn sample_n_spreads_over_the_gradient(){
+        let [red, green, blue] = t_primaries();
+        let g = Gradient::from_colors(&[red, blue], ColorSpace::Srgb, EdgeMode::Clamp);
+        assert_eq!(g.sample_n(3), vec![red, g.sample(0.5), blue]);
+        assert_eq!(g.sample_n(1), vec![red]);
+        assert!(g.sample_n(0).is_empty());
+        // Wrapped, the last color stops one step short of the first
+        let g = Gradient::from_colors(&[red, green, blue], ColorSpace::Srgb, EdgeMode::Wrap);
+        assert_eq!(g.sample_n(3), vec![red, green, blue]);
+    }
+
+    #[test]
+    fn empty_and_single_stop_gradients(){
+        let [red, green, blue] = t_primaries();
+        let mut g = Gradient::new(ColorSpace::Oklab, EdgeMode::Wrap);
+        assert_eq!(g.sample(0.3), Color::default());
+        assert_eq!(g.sample_n(2), vec![Color::default(); 2]);
+        g.add_stop(0.4, green);
+        assert_eq!((g.sample(0.0), g.sample(0.9)), (green, green));
+        // Stops are kept in order and inside 0 to 1
+        g.add_stop(2.0, blue).add_stop(0.1, red);
+        assert_eq!(g.get_stops().iter().map(|x| x.position).collect::<Vec<f32>>(), vec![0.1, 0.4, 1.0]);
+    }
+
+    #[test]
+    fn builtins_and_parsed_palettes(){
+        let [red, green, blue] = t_primaries();
+        for name in BUILTIN_PALETTES{
+            let p = Palette::builtin(name).unwrap();
+            assert_eq!(p.get_name(), name);
+            assert!(p.get_gradient().get_stops().len() >= 2);
+        }
+        assert!(Palette::builtin("plaid").is_none());
+        let rainbow = Palette::builtin("rainbow").unwrap();
+        assert_eq!(rainbow.sample(0.0), red);
+        assert_eq!(rainbow.sample(1.0), red);
+        assert_eq!(rainbow.sample_n(6)[2], green);
+
+        assert_eq!(Palette::parse(" Fire ").unwrap(), Palette::builtin("fire").unwrap());
+        let p = Palette::parse("red, rgb(0, 255, 0), #0000ff").unwrap();
+        assert_eq!(p.sample_n(3), vec![red, green, blue]);
+        assert_eq!(p.get_gradient().get_edge(), EdgeMode::Clamp);
+        for text in ["", "plaid", "red", "red, notacolor", "red, rgb(0, 255"]{
+            assert!(Palette::parse(text).is_err(), "{} parsed", text);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Position{
+    pub x: f32,
+    pub y: f32,
+    pub z: f32
+}
+
+impl Position{
+    pub fn new(x: f32, y: f32, z: f32) -> Position{
+        return Position { x, y, z };
+    }
+    pub fn lerp(&self, other: &Position, t: f32) -> Position{
+        return Position::new(
+            self.x + (other.x - self.x) * t,
+            self.y + (other.y - self.y) * t,
+            self.z + (other.z - self.z) * t
+        );
+    }
+}
+
+// Smallest and largest corner of the box containing every position
+pub fn bounding_box(positions: &Vec<Position>) -> Option<(Position, Position)>{
+    let first = positions.first()?;
+    let mut min = *first;
+    let mut max = *first;
+    for i in positions.iter().skip(1){
+        min = Position::new(min.x.min(i.x), min.y.min(i.y), min.z.min(i.z));
+        max = Position::new(max.x.max(i.x), max.y.max(i.y), max.z.max(i.z));
+    }
+    return Some((min, max));
+}
+
+// Rows of "id,index,x,y[,z]", blank lines, "#" comments and a header row are skipped
+pub fn parse_positions_csv(csv: &str) -> Result<Vec<(u32, usize, Position)>, String>{
+    let mut out: Vec<(u32, usize, Position)> = Vec::new();
+    for (line_number, line) in csv.lines().enumerate(){
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#'){
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|x| x.trim()).collect();
+        if fields.len() < 4{
+            return Err(format!("Line {}: expected id,index,x,y[,z]", line_number + 1));
+        }
+        let id = match fields[0].parse::<u32>(){
+            Ok(x) => x,
+            Err(_) if line_number == 0 => continue,
+            Err(_) => return Err(format!("Line {}: invalid id", line_number + 1))
+        };
+        let index = match fields[1].parse::<usize>(){
+            Ok(x) => x,
+            Err(_) => return Err(format!("Line {}: invalid index", line_number + 1))
+        };
+        let mut coords = [0.0f32; 3];
+        for (i, f) in fields[2..].iter().take(3).enumerate(){
+            coords[i] = match f.parse(){
+                Ok(x) => x,
+                Err(_) => return Err(format!("Line {}: invalid coordinate", line_number + 1))
+            };
+        }
+        out.push((id, index, Position::new(coords[0], coords[1], coords[2])));
+    }
+    return Ok(out);
+}
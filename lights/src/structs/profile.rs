@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 
-use crate::managers::light_manager::LightManager;
+use crate::managers::light_manager::{LightManager, StructureDiff};
+use super::assignment::LightAssignment;
 use super::color::Color;
+use super::palette::Palette;
 
 pub struct Profile{
     lights: LightManager,
@@ -9,12 +11,14 @@ pub struct Profile{
     on: bool,
     enabled: bool,
     data: HashMap<String, ProfileData>,
-    error: bool
+    assignment: LightAssignment,
+    error: bool,
+    layered: bool
 }
 
 impl Profile{
     pub fn new( name: String, on: bool, enabled: bool, lights: LightManager) -> Profile{
-        return Profile { name, on, enabled, lights, data: HashMap::new(), error: false };
+        return Profile { name, on, enabled, lights, data: HashMap::new(), assignment: LightAssignment::All, error: false, layered: false };
     }
     pub fn instance_name(&self) -> String{
         return self.name.clone();
@@ -40,6 +44,23 @@ impl Profile{
         self.error = error;
     }
 
+    // Layered instances are composited by their transparency, others cover
+    // every light they hold. Set on its own the first time any light is
+    // given a transparency, profiles that fade in from nothing set it up front.
+    pub fn is_layered(&self) -> bool{
+        return self.layered;
+    }
+    pub fn set_layered(&mut self, layered: bool){
+        self.layered = layered;
+    }
+
+    pub fn get_assignment(&self) -> &LightAssignment{
+        return &self.assignment;
+    }
+    pub fn set_assignment(&mut self, assignment: LightAssignment){
+        self.assignment = assignment;
+    }
+
     pub fn get_data(&self, key: &str) -> Option<&ProfileData>{
         return self.data.get(key);
     }
@@ -66,13 +87,28 @@ impl Profile{
         };
     }
 
+    pub fn get_palette(&self, key: &str) -> Option<Palette>{
+        return match self.get_data(key){
+            Some(ProfileData::Palette(x)) => Some(x.clone()),
+            _ => None
+        };
+    }
+
     pub fn set_data(&mut self, key: &str, value: ProfileData){
         self.data.insert(key.to_string(), value);
     }
+    // Stores a builtin palette by name, or one made from a list of colors
+    pub fn set_palette(&mut self, key: &str, text: &str) -> Result<(), String>{
+        self.set_data(key, ProfileData::palette(text)?);
+        return Ok(());
+    }
 
     pub fn m(&mut self) -> &mut LightManager{
         return &mut self.lights;
     }
+    pub fn get_lights(&self) -> &LightManager{
+        return &self.lights;
+    }
 
 }
 
@@ -81,7 +117,14 @@ pub enum ProfileData{
     Float(f32),
     String(String),
     Bool(bool),
-    Color(Color)
+    Color(Color),
+    Palette(Palette)
+}
+
+impl ProfileData{
+    pub fn palette(text: &str) -> Result<ProfileData, String>{
+        return Ok(ProfileData::Palette(Palette::parse(text)?));
+    }
 }
 
 
@@ -91,6 +134,8 @@ pub trait ProfileInterface{
 
     fn created(&self, _parent: &mut Profile){}
     fn destroy(&self, _parent: &mut Profile){}
+    // Called after the instance's lights were reconciled with the system
+    fn structure_changed(&self, _parent: &mut Profile, _diff: &StructureDiff){}
 }
 
 
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+
+use super::light_primitive::*;
+use super::light_types::LightingTypes;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentRange{
+    pub uid: String,
+    pub start: usize,
+    pub end: usize,
+    pub reversed: bool
+}
+
+impl SegmentRange{
+    // Indices in the order the segment walks them, end is exclusive
+    pub fn indices(&self, size: usize) -> Vec<usize>{
+        let end = self.end.min(size);
+        let start = self.start.min(end);
+        let mut out: Vec<usize> = (start..end).collect();
+        if self.reversed{
+            out.reverse();
+        }
+        return out;
+    }
+}
+
+// A named run of lights made of ranges from one or more lighting entries,
+// which are referred to by uid so a saved segment survives restarts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment{
+    name: String,
+    ranges: Vec<SegmentRange>
+}
+
+impl Segment{
+    pub fn new(name: String) -> Segment{
+        return Segment { name, ranges: Vec::new() };
+    }
+    pub fn add_range(&mut self, uid: String, start: usize, end: usize, reversed: bool) -> &mut Self{
+        self.ranges.push(SegmentRange { uid, start, end, reversed });
+        return self;
+    }
+    pub fn replace_uid(&mut self, old: &str, new: &str){
+        for i in &mut self.ranges{
+            if i.uid == old{
+                i.uid = new.to_string();
+            }
+        }
+    }
+    pub fn get_name(&self) -> String{
+        return self.name.clone();
+    }
+    pub fn get_ranges(&self) -> &Vec<SegmentRange>{
+        return &self.ranges;
+    }
+    // Every (id, index) in the order the segment walks them. Lights covered
+    // by more than one range only count where they are first reached, lookup
+    // gives the id and length behind each uid, None for ones that no longer exist.
+    pub fn walk(&self, lookup: impl Fn(&str) -> Option<(u32, usize)>) -> Vec<(u32, usize)>{
+        let mut seen: HashSet<(u32, usize)> = HashSet::new();
+        let mut out: Vec<(u32, usize)> = Vec::new();
+        for range in &self.ranges{
+            let (id, length) = match lookup(&range.uid){
+                None => continue,
+                Some(x) => x
+            };
+            for index in range.indices(length){
+                if seen.insert((id, index)){
+                    out.push((id, index));
+                }
+            }
+        }
+        return out;
+    }
+    pub fn get_uids(&self) -> Vec<String>{
+        let mut out: Vec<String> = self.ranges.iter().map(|x| x.uid.clone()).collect();
+        out.sort();
+        out.dedup();
+        return out;
+    }
+}
+
+// Borrows a segment together with the lights it covers so it can be drawn on
+// like any other LightVec, in the order of Segment::walk.
+pub struct SegmentView<'a>{
+    segment: &'a mut Segment,
+    lights: &'a mut HashMap<u32, LightingTypes>,
+    ids: &'a HashMap<String, u32>
+}
+
+impl<'a> SegmentView<'a>{
+    pub fn new(segment: &'a mut Segment, lights: &'a mut HashMap<u32, LightingTypes>, ids: &'a HashMap<String, u32>) -> SegmentView<'a>{
+        return SegmentView { segment, lights, ids };
+    }
+    fn get_ids(&self) -> Vec<u32>{
+        return self.segment.get_uids().iter().filter_map(|x| self.ids.get(x).cloned()).collect();
+    }
+    pub fn get_segment(&self) -> &Segment{
+        return self.segment;
+    }
+}
+
+impl<'a> LightVec for SegmentView<'a>{
+    fn _get_lights_mut(&mut self) -> Vec<&mut Light>{
+        let ids = self.get_ids();
+        let mut by_id: HashMap<u32, Vec<Option<&mut Light>>> = HashMap::new();
+        for (id, i) in self.lights.iter_mut(){
+            if ids.contains(id){
+                by_id.insert(*id, i._get_lights_mut().into_iter().map(Some).collect());
+            }
+        }
+
+        let walk = self.segment.walk(|uid| self.ids.get(uid).and_then(|id| by_id.get(id).map(|x| (*id, x.len()))));
+        let mut out: Vec<&mut Light> = Vec::with_capacity(walk.len());
+        for (id, index) in walk{
+            if let Some(x) = by_id.get_mut(&id).and_then(|x| x[index].take()){
+                out.push(x);
+            }
+        }
+        return out;
+    }
+    fn _get_lights(&self) -> Vec<&Light>{
+        let by_id: HashMap<u32, Vec<&Light>> = self.get_ids().into_iter()
+            .filter_map(|id| self.lights.get(&id).map(|x| (id, x._get_lights())))
+            .collect();
+        return self.segment.walk(|uid| self.ids.get(uid).and_then(|id| by_id.get(id).map(|x| (*id, x.len())))).into_iter()
+            .map(|(id, index)| by_id[&id][index])
+            .collect();
+    }
+    fn get_name(&self) -> String{
+        return self.segment.name.clone();
+    }
+    fn set_name(&mut self, name: String){
+        self.segment.name = name;
+    }
+    // A segment has no structure of its own, it follows the lights it covers
+    fn sync_structure(&mut self, _state: &Self){}
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::managers::light_manager::LightManager;
+    use crate::structs::color::Color;
+    use crate::structs::light_types::LightStrip;
+
+    fn t_strip(lights: &mut LightManager, name: &str, length: usize) -> (u32, String){
+        let id = lights.add_light(LightStrip::new_enum(name.to_string(), 1, length, RgbLight::default_enum()));
+        return (id, lights.get_uid(id).unwrap());
+    }
+    fn t_reds(lights: &LightManager, id: u32) -> Vec<u8>{
+        return lights.get_light(id).unwrap()._get_lights().iter().map(|x| x.get_color().get_red()).collect();
+    }
+
+    #[test]
+    fn segments_walk_ranges_in_order(){
+        let mut lights = LightManager::new();
+        let ((a, a_uid), (b, b_uid)) = (t_strip(&mut lights, "a", 5), t_strip(&mut lights, "b", 5));
+        let mut segment = Segment::new("s".to_string());
+        segment.add_range(a_uid, 1, 4, true).add_range(b_uid, 0, 2, false);
+        lights.add_segment(segment);
+
+        let mut view = lights.get_segment_view("s").unwrap();
+        assert_eq!(view.size(), 5);
+        for i in 0..5{
+            view.set_color_index(Color::new(i as u8 + 1, 0, 0), i);
+        }
+        assert_eq!(t_reds(&lights, a), vec![0, 3, 2, 1, 0]);
+        assert_eq!(t_reds(&lights, b), vec![4, 5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn overlapping_ranges_cover_each_light_once(){
+        let mut lights = LightManager::new();
+        let (a, uid) = t_strip(&mut lights, "a", 6);
+        let mut segment = Segment::new("s".to_string());
+        segment.add_range(uid.clone(), 0, 4, false).add_range(uid.clone(), 2, 6, true).add_range(uid, 8, 10, false);
+        assert_eq!(segment.walk(|_| Some((a, 6))), vec![(a, 0), (a, 1), (a, 2), (a, 3), (a, 5), (a, 4)]);
+        lights.add_segment(segment);
+
+        let mut view = lights.get_segment_view("s").unwrap();
+        assert_eq!(view._get_lights().len(), view._get_lights_mut().len());
+        view.set_color_index(Color::new(9, 0, 0), 4);
+        assert_eq!(view._get_lights()[4].get_color().get_red(), 9);
+        assert_eq!(t_reds(&lights, a), vec![0, 0, 0, 0, 0, 9]);
+    }
+
+    #[test]
+    fn ranges_on_missing_lights_are_skipped(){
+        let mut lights = LightManager::new();
+        let (_, uid) = t_strip(&mut lights, "a", 3);
+        let mut segment = Segment::new("s".to_string());
+        segment.add_range("gone".to_string(), 0, 3, false).add_range(uid, 0, 3, false);
+        lights.add_segment(segment);
+        assert_eq!(lights.get_segment_view("s").unwrap().size(), 3);
+    }
+}
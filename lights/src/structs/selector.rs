@@ -0,0 +1,352 @@
+use std::collections::BTreeSet;
+
+use crate::managers::light_manager::LightManager;
+use super::light_primitive::*;
+use super::light_types::LightingTypes;
+
+// Selectors address lights with terms such as
+//   uid:porch-left,porch-right   name:"main strip"[10..50]   tag:accent
+//   room:kitchen   type:bulb   segment:stairs   *
+// combined with "&" (both), "|" (either), "!" (not) and parentheses.
+// A trailing [a..b], [a..], [..b] or [a] keeps only those indices. On a
+// segment they count along the segment, starting from its first light,
+// elsewhere they are each light's own indices.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector{
+    All,
+    Uids(Vec<String>),
+    Name(String),
+    Tag(String),
+    Room(String),
+    Type(String),
+    Segment(String),
+    Range(Box<Selector>, Option<usize>, Option<usize>),
+    And(Box<Selector>, Box<Selector>),
+    Or(Box<Selector>, Box<Selector>),
+    Not(Box<Selector>)
+}
+
+struct Parser<'a>{
+    chars: Vec<char>,
+    pos: usize,
+    source: &'a str
+}
+
+impl<'a> Parser<'a>{
+    fn error(&self, message: &str) -> String{
+        return format!("{} at position {} in \"{}\"", message, self.pos, self.source);
+    }
+    fn skip_space(&mut self){
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace(){
+            self.pos += 1;
+        }
+    }
+    fn peek(&mut self) -> Option<char>{
+        self.skip_space();
+        return self.chars.get(self.pos).cloned();
+    }
+    fn eat(&mut self, c: char) -> bool{
+        if self.peek() == Some(c){
+            self.pos += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn word(&mut self) -> String{
+        self.skip_space();
+        let mut out = String::new();
+        while let Some(c) = self.chars.get(self.pos){
+            if c.is_alphanumeric() || *c == '_' || *c == '-' || (*c == '.' && self.chars.get(self.pos + 1) != Some(&'.')){
+                out.push(*c);
+                self.pos += 1;
+            }else{
+                break;
+            }
+        }
+        return out;
+    }
+    fn value(&mut self) -> Result<String, String>{
+        if self.peek() == Some('"'){
+            self.pos += 1;
+            let mut out = String::new();
+            while let Some(c) = self.chars.get(self.pos).cloned(){
+                self.pos += 1;
+                match c{
+                    '"' => return Ok(out),
+                    '\\' => match self.chars.get(self.pos).cloned(){
+                        Some(x) => {
+                            out.push(x);
+                            self.pos += 1;
+                        },
+                        None => break
+                    },
+                    _ => out.push(c)
+                }
+            }
+            return Err(self.error("Unterminated string"));
+        }
+        let out = self.word();
+        if out.is_empty(){
+            return Err(self.error("Expected a value"));
+        }
+        return Ok(out);
+    }
+    fn number(&mut self) -> Option<usize>{
+        self.skip_space();
+        let start = self.pos;
+        while self.pos < self.chars.len() && self.chars[self.pos].is_ascii_digit(){
+            self.pos += 1;
+        }
+        if start == self.pos{
+            return None;
+        }
+        return self.chars[start..self.pos].iter().collect::<String>().parse().ok();
+    }
+
+    fn expr(&mut self) -> Result<Selector, String>{
+        let mut out = self.term()?;
+        while self.eat('|'){
+            out = Selector::Or(Box::new(out), Box::new(self.term()?));
+        }
+        return Ok(out);
+    }
+    fn term(&mut self) -> Result<Selector, String>{
+        let mut out = self.factor()?;
+        while self.eat('&'){
+            out = Selector::And(Box::new(out), Box::new(self.factor()?));
+        }
+        return Ok(out);
+    }
+    fn factor(&mut self) -> Result<Selector, String>{
+        if self.eat('!'){
+            return Ok(Selector::Not(Box::new(self.factor()?)));
+        }
+        let base = if self.eat('('){
+            let inner = self.expr()?;
+            if !self.eat(')'){
+                return Err(self.error("Expected )"));
+            }
+            inner
+        }else{
+            self.atom()?
+        };
+        return self.range(base);
+    }
+    fn atom(&mut self) -> Result<Selector, String>{
+        if self.eat('*'){
+            return Ok(Selector::All);
+        }
+        let key = self.word();
+        if !self.eat(':'){
+            return Err(self.error("Expected key:value"));
+        }
+        return match key.as_str(){
+            "uid" => {
+                let mut uids: Vec<String> = vec![self.value()?];
+                while self.eat(','){
+                    uids.push(self.value()?);
+                }
+                Ok(Selector::Uids(uids))
+            },
+            "name" => Ok(Selector::Name(self.value()?)),
+            "tag" => Ok(Selector::Tag(self.value()?)),
+            "room" => Ok(Selector::Room(self.value()?)),
+            "type" => Ok(Selector::Type(self.value()?)),
+            "segment" => Ok(Selector::Segment(self.value()?)),
+            _ => Err(self.error(&format!("Unknown selector key \"{}\"", key)))
+        };
+    }
+    fn range(&mut self, base: Selector) -> Result<Selector, String>{
+        if !self.eat('['){
+            return Ok(base);
+        }
+        let start = self.number();
+        let out = if self.eat('.'){
+            if !self.eat('.'){
+                return Err(self.error("Expected .."));
+            }
+            Selector::Range(Box::new(base), start, self.number())
+        }else{
+            match start{
+                Some(x) => Selector::Range(Box::new(base), Some(x), Some(x + 1)),
+                None => return Err(self.error("Expected an index or range"))
+            }
+        };
+        if !self.eat(']'){
+            return Err(self.error("Expected ]"));
+        }
+        return Ok(out);
+    }
+}
+
+fn type_matches(light: &LightingTypes, name: &str) -> bool{
+    return matches!((light, name),
+        (LightingTypes::LightStrip(_), "strip") |
+        (LightingTypes::LightMatrix(_), "matrix") |
+        (LightingTypes::Bulb(_), "bulb") |
+        (LightingTypes::BulbGroup(_), "group")
+    );
+}
+
+fn segment_walk(lights: &LightManager, name: &str) -> Vec<(u32, usize)>{
+    return match lights.get_segment(name){
+        None => Vec::new(),
+        Some(s) => s.walk(|uid| {
+            let id = lights.get_id_by_uid(uid)?;
+            return Some((id, lights.get_light(id)?.size()));
+        })
+    };
+}
+
+fn every_index(lights: &LightManager, ids: Vec<u32>) -> BTreeSet<(u32, usize)>{
+    let mut out: BTreeSet<(u32, usize)> = BTreeSet::new();
+    for id in ids{
+        if let Some(x) = lights.get_light(id){
+            for index in 0..x.size(){
+                out.insert((id, index));
+            }
+        }
+    }
+    return out;
+}
+
+impl Selector{
+    pub fn parse(text: &str) -> Result<Selector, String>{
+        let mut parser = Parser { chars: text.chars().collect(), pos: 0, source: text };
+        let out = parser.expr()?;
+        if parser.peek().is_some(){
+            return Err(parser.error("Unexpected input"));
+        }
+        return Ok(out);
+    }
+
+    fn resolve_set(&self, lights: &LightManager) -> BTreeSet<(u32, usize)>{
+        return match self{
+            Selector::All => every_index(lights, lights.get_all_ids()),
+            Selector::Uids(x) => every_index(lights, x.iter().filter_map(|uid| lights.get_id_by_uid(uid)).collect()),
+            Selector::Name(x) => every_index(lights, lights.get_all_ids().into_iter()
+                .filter(|id| lights.get_light(*id).unwrap().get_name() == *x)
+                .collect()),
+            Selector::Tag(x) => every_index(lights, lights.get_ids_with_tag(x)),
+            Selector::Room(x) => every_index(lights, lights.get_room_ids(x)),
+            Selector::Type(x) => every_index(lights, lights.get_all_ids().into_iter()
+                .filter(|id| type_matches(lights.get_light(*id).unwrap(), x))
+                .collect()),
+            Selector::Segment(x) => segment_walk(lights, x).into_iter().collect(),
+            Selector::Range(x, start, end) => match &**x{
+                Selector::Segment(name) => {
+                    let walk = segment_walk(lights, name);
+                    let end = end.unwrap_or(walk.len()).min(walk.len());
+                    let start = start.unwrap_or(0).min(end);
+                    walk[start..end].iter().cloned().collect()
+                },
+                _ => x.resolve_set(lights).into_iter()
+                    .filter(|(_, i)| start.is_none_or(|s| *i >= s) && end.is_none_or(|e| *i < e))
+                    .collect()
+            },
+            Selector::And(a, b) => a.resolve_set(lights).intersection(&b.resolve_set(lights)).cloned().collect(),
+            Selector::Or(a, b) => a.resolve_set(lights).union(&b.resolve_set(lights)).cloned().collect(),
+            Selector::Not(x) => every_index(lights, lights.get_all_ids()).difference(&x.resolve_set(lights)).cloned().collect()
+        };
+    }
+
+    // Every (id, index) the selector addresses, sorted
+    pub fn resolve(&self, lights: &LightManager) -> Vec<(u32, usize)>{
+        return self.resolve_set(lights).into_iter().collect();
+    }
+    pub fn resolve_ids(&self, lights: &LightManager) -> Vec<u32>{
+        let mut out: Vec<u32> = self.resolve(lights).into_iter().map(|x| x.0).collect();
+        out.dedup();
+        return out;
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use super::super::light_types::*;
+    use super::super::segment::Segment;
+
+    // A 60 light strip and two bulbs, the strip and one bulb tagged accent
+    fn t_lights() -> (LightManager, u32, u32, u32){
+        let mut lights = LightManager::new();
+        let strip = lights.add_light(LightStrip::new_enum("main strip".to_string(), 1, 60, RgbLight::default_enum()));
+        let porch = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.1".to_string(), "porch".to_string())));
+        let hall = lights.add_light(LightingTypes::Bulb(Bulb::new("10.0.0.2".to_string(), "hall".to_string())));
+        lights.add_tag(strip, "accent".to_string());
+        lights.add_tag(porch, "accent".to_string());
+        return (lights, strip, porch, hall);
+    }
+
+    #[test]
+    fn parses_quoted_names_with_ranges(){
+        let s = Selector::parse("name:\"main strip\"[10..50]").unwrap();
+        assert_eq!(s, Selector::Range(Box::new(Selector::Name("main strip".to_string())), Some(10), Some(50)));
+        let uids = Selector::Uids(vec!["a".to_string(), "b-2".to_string()]);
+        assert_eq!(Selector::parse("uid:a,b-2[..5]").unwrap(), Selector::Range(Box::new(uids.clone()), None, Some(5)));
+        assert_eq!(Selector::parse("uid:a, b-2[7]").unwrap(), Selector::Range(Box::new(uids), Some(7), Some(8)));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or(){
+        let s = Selector::parse("tag:accent & type:bulb").unwrap();
+        assert_eq!(s, Selector::And(Box::new(Selector::Tag("accent".to_string())), Box::new(Selector::Type("bulb".to_string()))));
+        let s = Selector::parse("uid:a | tag:a & !type:bulb").unwrap();
+        assert_eq!(s, Selector::Or(
+            Box::new(Selector::Uids(vec!["a".to_string()])),
+            Box::new(Selector::And(
+                Box::new(Selector::Tag("a".to_string())),
+                Box::new(Selector::Not(Box::new(Selector::Type("bulb".to_string()))))
+            ))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_selectors(){
+        for text in ["name:\"main strip", "color:red", "id:1", "uid:", "uid:a,", "tag:a &", "(tag:a", "tag:a[3..", "tag:a b"]{
+            assert!(Selector::parse(text).is_err(), "{} parsed", text);
+        }
+    }
+
+    #[test]
+    fn resolves_ranges_of_named_lights(){
+        let (lights, strip, _, _) = t_lights();
+        let out = Selector::parse("name:\"main strip\"[10..50]").unwrap().resolve(&lights);
+        assert_eq!(out, (10..50).map(|i| (strip, i)).collect::<Vec<(u32, usize)>>());
+        assert!(Selector::parse("name:main[10..50]").unwrap().resolve(&lights).is_empty());
+    }
+
+    #[test]
+    fn resolves_intersections(){
+        let (lights, strip, porch, hall) = t_lights();
+        let s = Selector::parse("tag:accent & type:bulb").unwrap();
+        assert_eq!(s.resolve(&lights), vec![(porch, 0)]);
+        assert_eq!(s.resolve_ids(&lights), vec![porch]);
+        assert_eq!(Selector::parse("type:bulb & !tag:accent").unwrap().resolve_ids(&lights), vec![hall]);
+        assert_eq!(Selector::parse("tag:accent").unwrap().resolve_ids(&lights), vec![strip, porch]);
+    }
+
+    #[test]
+    fn ranges_on_segments_count_along_the_segment(){
+        let (mut lights, strip, _, _) = t_lights();
+        let mut segment = Segment::new("stairs".to_string());
+        segment.add_range("main-strip".to_string(), 40, 80, false);
+        lights.add_segment(segment);
+        let mut landing = Segment::new("landing".to_string());
+        landing.add_range("main-strip".to_string(), 0, 5, true);
+        lights.add_segment(landing);
+
+        let out = Selector::parse("segment:stairs[0..10]").unwrap().resolve(&lights);
+        assert_eq!(out, (40..50).map(|i| (strip, i)).collect::<Vec<(u32, usize)>>());
+        let out = Selector::parse("segment:stairs[15]").unwrap().resolve(&lights);
+        assert_eq!(out, vec![(strip, 55)]);
+        // Reversed segments start from their far end
+        let out = Selector::parse("segment:landing[..2]").unwrap().resolve(&lights);
+        assert_eq!(out, vec![(strip, 3), (strip, 4)]);
+        assert!(Selector::parse("segment:stairs[100..]").unwrap().resolve(&lights).is_empty());
+        // Anything else keeps the strip's own indices
+        let out = Selector::parse("(segment:stairs & tag:accent)[0..45]").unwrap().resolve(&lights);
+        assert_eq!(out, (40..45).map(|i| (strip, i)).collect::<Vec<(u32, usize)>>());
+    }
+}
@@ -1,4 +1,3 @@
-use crate::structs::color::Color;
 
 pub fn mut_vec_to_vec_mut<T>(v: &mut Vec<T>) -> Vec<&mut T>{
     let s = v.len();
@@ -19,42 +18,18 @@ pub fn ref_vec_to_vec_ref<T>(v: &Vec<T>) -> Vec<&T>{
         .collect();
 }
 
-pub fn temp_to_color(temp: u32) -> Color{
-    // https://tannerhelland.com/2012/09/18/convert-temperature-rgb-algorithm-code.html
-
-    let (mut r, mut g, mut b);
-    if temp <= 66 {
-        r = 255.0;
-    }else{
-        r = temp.try_into().unwrap();
-        r = r - 60.0;
-        r = 329.698727488 * (f64::powf(r,-0.1332047592));
-        r = r.clamp(0.0, 255.0);
-    };
-
-    if temp <= 66{
-        g = temp.try_into().unwrap();
-        g = 99.4708025861 * f64::ln(g) - 161.1195681661;
-        
-    }else{
-        g = temp.try_into().unwrap();
-        g = g -60.0;
-        g = 161.1195681661 * f64::powf(g, -0.0755148492);
-    }
-    g = g.clamp(0.0, 255.0);
-
-    if temp >= 66{
-        b = 255.0;
-    }else{
-        if temp <= 19{
-            b = 0.0;
-        }else{
-            b = temp.try_into().unwrap();
-            b = b - 10.0;
-            b = 138.5177312231 * f64::ln(b) - 305.0447927307;
-            b = b.clamp(0.0, 255.0);
+// Lowercase letters and digits with single dashes between words, so "Main Strip #2" is "main-strip-2"
+pub fn slugify(name: &str) -> String{
+    let mut out = String::new();
+    for c in name.chars(){
+        if c.is_alphanumeric(){
+            out.extend(c.to_lowercase());
+        }else if !out.is_empty() && !out.ends_with('-'){
+            out.push('-');
         }
     }
-
-    return Color::new(f64::round(r) as u8, f64::round(g) as u8, f64::round(b) as u8);
-}
\ No newline at end of file
+    while out.ends_with('-'){
+        out.pop();
+    }
+    return out;
+}
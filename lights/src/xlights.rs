@@ -0,0 +1,292 @@
+use std::f32::consts::PI;
+use std::io;
+use log::*;
+
+use crate::structs::light_primitive::*;
+use crate::structs::light_types::*;
+use crate::structs::position::Position;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModelKind{
+    SingleLine,
+    Matrix,
+    Arches,
+    Custom
+}
+
+// Models claiming more pixels than this are rejected rather than allocated
+pub const MAX_MODEL_PIXELS: usize = 1 << 20;
+
+#[derive(Debug, Clone)]
+pub struct XlightsModel{
+    pub name: String,
+    pub kind: ModelKind,
+    pub pixel_count: usize,
+    pub start_channel: Option<u32>,
+    // World positions in wiring order
+    pub positions: Vec<Position>,
+    // Set for matrices
+    pub layout: Option<MatrixLayout>
+}
+
+impl XlightsModel{
+    // Matrices become a LightMatrix, everything else a LightStrip
+    pub fn to_light(&self) -> LightingTypes{
+        // xLights start channels are 1 based
+        let start_channel = self.start_channel.map(|x| x.saturating_sub(1));
+        if let Some(layout) = self.layout{
+            let mut out = LightMatrix::new(self.name.clone(), 0, layout, RgbLight::default_enum());
+            out.set_start_channel(start_channel);
+            return LightingTypes::LightMatrix(out);
+        }
+        let mut out = LightStrip::new(self.name.clone(), 0, self.pixel_count, RgbLight::default_enum());
+        out.set_start_channel(start_channel);
+        if self.positions.len() == self.pixel_count{
+            out.set_positions(Some(self.positions.clone()));
+        }
+        return LightingTypes::LightStrip(out);
+    }
+}
+
+fn attr_f32(node: &roxmltree::Node, name: &str, default: f32) -> f32{
+    return node.attribute(name).and_then(|x| x.trim().parse().ok()).unwrap_or(default);
+}
+fn attr_usize(node: &roxmltree::Node, name: &str, default: usize) -> usize{
+    return node.attribute(name).and_then(|x| x.trim().parse().ok()).unwrap_or(default);
+}
+fn pixel_count(a: usize, b: usize) -> Result<usize, String>{
+    return match a.checked_mul(b){
+        Some(x) if x <= MAX_MODEL_PIXELS => Ok(x),
+        _ => Err(format!("more than {} pixels", MAX_MODEL_PIXELS))
+    };
+}
+
+// Model coordinates are placed with unit spacing between nodes, scaled by the
+// model's scale and moved to its world position
+fn place(node: &roxmltree::Node, local: Vec<Position>) -> Vec<Position>{
+    let origin = Position::new(attr_f32(node, "WorldPosX", 0.0), attr_f32(node, "WorldPosY", 0.0), attr_f32(node, "WorldPosZ", 0.0));
+    let scale = Position::new(attr_f32(node, "ScaleX", 1.0), attr_f32(node, "ScaleY", 1.0), attr_f32(node, "ScaleZ", 1.0));
+    return local.iter()
+        .map(|p| Position::new(origin.x + p.x * scale.x, origin.y + p.y * scale.y, origin.z + p.z * scale.z))
+        .collect();
+}
+
+fn single_line(node: &roxmltree::Node) -> Result<(usize, Vec<Position>), String>{
+    let count = pixel_count(attr_usize(node, "parm1", 1), attr_usize(node, "parm2", 0))?;
+    let start = Position::new(attr_f32(node, "WorldPosX", 0.0), attr_f32(node, "WorldPosY", 0.0), attr_f32(node, "WorldPosZ", 0.0));
+    let end = Position::new(start.x + attr_f32(node, "X2", 0.0), start.y + attr_f32(node, "Y2", 0.0), start.z + attr_f32(node, "Z2", 0.0));
+
+    let mut out: Vec<Position> = Vec::with_capacity(count);
+    for i in 0..count{
+        let t = if count > 1 {i as f32 / (count - 1) as f32} else {0.0};
+        out.push(start.lerp(&end, t));
+    }
+    return Ok((count, out));
+}
+
+// Strands zig-zag from the corner given by StartSide (top or bottom, bottom
+// by default) and Dir (left or right), along rows for horizontal matrices and
+// columns for vertical ones
+fn matrix(node: &roxmltree::Node, vertical: bool) -> Result<(MatrixLayout, Vec<Position>), String>{
+    let strings = attr_usize(node, "parm1", 1);
+    let per_string = attr_usize(node, "parm2", 0);
+    let strands = attr_usize(node, "parm3", 1).max(1);
+    pixel_count(strings, per_string)?;
+
+    let along = (per_string / strands).max(1);
+    let across = pixel_count(strings, strands)?;
+    let (width, height) = if vertical {(across, along)} else {(along, across)};
+    pixel_count(width, height)?;
+    let mut layout = MatrixLayout::new(width, height);
+    layout.order = if vertical {WiringOrder::Columns} else {WiringOrder::Rows};
+    layout.serpentine = true;
+    let top = node.attribute("StartSide") == Some("T");
+    let right = node.attribute("Dir") == Some("R");
+    layout.start_corner = match (top, right){
+        (true, false) => StartCorner::TopLeft,
+        (true, true) => StartCorner::TopRight,
+        (false, false) => StartCorner::BottomLeft,
+        (false, true) => StartCorner::BottomRight
+    };
+
+    let mut local: Vec<Position> = vec![Position::default(); layout.size()];
+    for y in 0..height{
+        for x in 0..width{
+            if let Some(i) = layout.xy_to_index(x, y){
+                local[i] = Position::new(x as f32 - (width as f32 - 1.0) / 2.0, (height - 1 - y) as f32 - (height as f32 - 1.0) / 2.0, 0.0);
+            }
+        }
+    }
+    return Ok((layout, place(node, local)));
+}
+
+fn arches(node: &roxmltree::Node) -> Result<(usize, Vec<Position>), String>{
+    let count = attr_usize(node, "parm1", 1);
+    let per_arch = attr_usize(node, "parm2", 0);
+    let total = pixel_count(count, per_arch)?;
+
+    let mut local: Vec<Position> = Vec::with_capacity(total);
+    for a in 0..count{
+        for i in 0..per_arch{
+            let angle = if per_arch > 1 {PI * i as f32 / (per_arch - 1) as f32} else {PI / 2.0};
+            let x = a as f32 * 2.0 + 1.0 - angle.cos() - count as f32;
+            local.push(Position::new(x, angle.sin(), 0.0));
+        }
+    }
+    return Ok((total, place(node, local)));
+}
+
+// Custom models are a grid of node numbers, "," between columns, ";" between
+// rows and "|" between depth layers
+fn custom(node: &roxmltree::Node) -> Result<(usize, Vec<Position>), String>{
+    let data = node.attribute("CustomModel").unwrap_or("");
+    let mut cells: Vec<(usize, Position)> = Vec::new();
+    for (z, layer) in data.split('|').enumerate(){
+        for (y, row) in layer.split(';').enumerate(){
+            for (x, cell) in row.split(',').enumerate(){
+                if let Ok(n) = cell.trim().parse::<usize>(){
+                    if n > 0{
+                        cells.push((n, Position::new(x as f32, -(y as f32), z as f32)));
+                    }
+                }
+            }
+        }
+    }
+    let count = pixel_count(cells.iter().map(|(n, _)| *n).max().unwrap_or(0), 1)?;
+    let mut local: Vec<Position> = vec![Position::default(); count];
+    for (n, p) in cells{
+        local[n - 1] = p;
+    }
+    return Ok((count, place(node, local)));
+}
+
+pub fn parse_models(xml: &str) -> io::Result<Vec<XlightsModel>>{
+    let doc = match roxmltree::Document::parse(xml){
+        Ok(x) => x,
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    };
+
+    let mut out: Vec<XlightsModel> = Vec::new();
+    for node in doc.descendants().filter(|x| x.has_tag_name("model")){
+        let name = node.attribute("name").unwrap_or("").to_string();
+        let display = node.attribute("DisplayAs").unwrap_or("");
+        let parsed = match display{
+            "Single Line" => single_line(&node).map(|(c, p)| (ModelKind::SingleLine, c, p, None)),
+            "Horiz Matrix" | "Vert Matrix" => matrix(&node, display == "Vert Matrix")
+                .map(|(l, p)| (ModelKind::Matrix, l.size(), p, Some(l))),
+            "Arches" => arches(&node).map(|(c, p)| (ModelKind::Arches, c, p, None)),
+            "Custom" => custom(&node).map(|(c, p)| (ModelKind::Custom, c, p, None)),
+            _ => {
+                warn!("Skipping xLights model {} with unsupported type {}", name, display);
+                continue;
+            }
+        };
+        let (kind, pixel_count, positions, layout) = match parsed{
+            Ok(x) => x,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("xLights model {} has {}", name, e)))
+        };
+        // Only absolute start channels are kept, chained or controller relative ones are dropped
+        let start_channel = node.attribute("StartChannel").and_then(|x| x.trim().parse().ok());
+        out.push(XlightsModel { name, kind, pixel_count, start_channel, positions, layout });
+    }
+    return Ok(out);
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn t_models(models: &str) -> io::Result<Vec<XlightsModel>>{
+        return parse_models(&format!("<xrgb><models>{}</models></xrgb>", models));
+    }
+    fn t_model(model: &str) -> XlightsModel{
+        let mut out = t_models(model).unwrap();
+        assert_eq!(out.len(), 1);
+        return out.remove(0);
+    }
+
+    #[test]
+    fn single_lines_run_from_start_to_end(){
+        let m = t_model(r#"<model name="eaves" DisplayAs="Single Line" parm1="2" parm2="5" StartChannel="31"
+            WorldPosX="10" WorldPosY="2" WorldPosZ="0" X2="9" Y2="0" Z2="0"/>"#);
+        assert_eq!(m.kind, ModelKind::SingleLine);
+        assert_eq!(m.pixel_count, 10);
+        assert_eq!(m.positions.first(), Some(&Position::new(10.0, 2.0, 0.0)));
+        assert_eq!(m.positions.last(), Some(&Position::new(19.0, 2.0, 0.0)));
+        match m.to_light(){
+            LightingTypes::LightStrip(x) => {
+                assert_eq!(x.get_length(), 10);
+                assert_eq!(x.get_start_channel(), Some(30));
+            },
+            _ => panic!("Expected a strip")
+        }
+    }
+
+    #[test]
+    fn matrices_become_light_matrices(){
+        let m = t_model(r#"<model name="panel" DisplayAs="Horiz Matrix" parm1="4" parm2="12" parm3="2" StartSide="T" Dir="R" StartChannel="1"/>"#);
+        assert_eq!(m.kind, ModelKind::Matrix);
+        assert_eq!(m.pixel_count, 48);
+        let layout = m.layout.unwrap();
+        assert_eq!((layout.get_width(), layout.get_height()), (6, 8));
+        assert_eq!(layout.order, WiringOrder::Rows);
+        assert_eq!(layout.start_corner, StartCorner::TopRight);
+        assert!(layout.serpentine);
+        // The first pixel sits in the top right corner
+        assert_eq!(m.positions[0], Position::new(2.5, 3.5, 0.0));
+        match m.to_light(){
+            LightingTypes::LightMatrix(x) => {
+                assert_eq!(x.get_layout(), layout);
+                assert_eq!(x.get_start_channel(), Some(0));
+            },
+            _ => panic!("Expected a matrix")
+        }
+
+        let m = t_model(r#"<model name="tree" DisplayAs="Vert Matrix" parm1="3" parm2="10"/>"#);
+        let layout = m.layout.unwrap();
+        assert_eq!((layout.get_width(), layout.get_height()), (3, 10));
+        assert_eq!(layout.order, WiringOrder::Columns);
+        assert_eq!(layout.start_corner, StartCorner::BottomLeft);
+        assert_eq!(m.start_channel, None);
+    }
+
+    #[test]
+    fn arches_and_custom_models(){
+        let m = t_model(r#"<model name="arches" DisplayAs="Arches" parm1="3" parm2="7"/>"#);
+        assert_eq!(m.kind, ModelKind::Arches);
+        assert_eq!((m.pixel_count, m.positions.len()), (21, 21));
+        assert!(m.positions.iter().all(|p| p.y >= -0.001 && p.y <= 1.001));
+
+        let m = t_model(r#"<model name="star" DisplayAs="Custom" CustomModel=",2,;1,,3|,,4" ScaleX="2"/>"#);
+        assert_eq!(m.kind, ModelKind::Custom);
+        assert_eq!(m.pixel_count, 4);
+        assert_eq!(m.positions[0], Position::new(0.0, -1.0, 0.0));
+        assert_eq!(m.positions[1], Position::new(2.0, 0.0, 0.0));
+        assert_eq!(m.positions[2], Position::new(4.0, -1.0, 0.0));
+        assert_eq!(m.positions[3], Position::new(4.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn unsupported_models_are_skipped(){
+        let out = t_models(r#"<model name="a" DisplayAs="Sphere"/><model name="b" DisplayAs="Single Line" parm2="3"/>"#).unwrap();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].name, "b");
+    }
+
+    #[test]
+    fn oversized_models_are_rejected(){
+        let huge = usize::MAX.to_string();
+        for model in [
+            format!(r#"<model name="a" DisplayAs="Single Line" parm1="{}" parm2="2"/>"#, huge),
+            format!(r#"<model name="a" DisplayAs="Horiz Matrix" parm1="2" parm2="{}"/>"#, huge),
+            format!(r#"<model name="a" DisplayAs="Vert Matrix" parm1="{}" parm2="1" parm3="4"/>"#, huge),
+            format!(r#"<model name="a" DisplayAs="Arches" parm1="{}" parm2="{}"/>"#, huge, huge),
+            format!(r#"<model name="a" DisplayAs="Custom" CustomModel="1,{}"/>"#, MAX_MODEL_PIXELS + 1),
+            format!(r#"<model name="a" DisplayAs="Custom" CustomModel="{}"/>"#, huge)
+        ]{
+            let error = t_models(&model).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", model);
+        }
+        assert!(parse_models("<models><model").is_err());
+    }
+}
@@ -1,10 +1,105 @@
 use lights::lighting_system::*;
+use lights::output::FrameSink;
+use lights::output::raw_stream::RawStreamOutput;
+use lights::output::recorder::FrameRecorder;
+use lights::output::fseq_recorder::FseqRecorder;
+#[cfg(unix)]
+use lights::output::shm::ShmRingOutput;
 use lights::structs::{light_types::*, light_primitive::*};
 use log::*;
 use std::env;
 
 
 
+fn stream(system: &mut System, args: &[String]){
+    let output: std::io::Result<Box<dyn FrameSink>> = match args.first().map(|x| x.as_str()){
+        None | Some("stdout") => Ok(Box::new(RawStreamOutput::stdout())),
+        Some("fifo") => match args.get(1){
+            Some(path) => RawStreamOutput::fifo(path).map(|x| Box::new(x) as Box<dyn FrameSink>),
+            None => {error!("Usage: stream fifo <path>"); return}
+        },
+        #[cfg(unix)]
+        Some("socket") => match args.get(1){
+            Some(path) => RawStreamOutput::unix_socket(path).map(|x| Box::new(x) as Box<dyn FrameSink>),
+            None => {error!("Usage: stream socket <path>"); return}
+        },
+        #[cfg(unix)]
+        Some("shm") => match args.get(1){
+            Some(name) => ShmRingOutput::new(name, 8, 4096).map(|x| Box::new(x) as Box<dyn FrameSink>),
+            None => {error!("Usage: stream shm <name>"); return}
+        },
+        Some(x) => {error!("Unknown stream target {}", x); return}
+    };
+    match output{
+        Ok(x) => system.add_output(x),
+        Err(e) => {error!("Failed to open stream: {}", e); return}
+    }
+
+    system.set_frame_rate(Some(30.0));
+    while system.output_count() > 0{
+        system.update();
+    }
+}
+
+fn record(system: &mut System, args: &[String]){
+    let mut profile: Option<String> = None;
+    let mut frames: usize = 300;
+    let mut seconds: Option<f64> = None;
+    let mut step_ms: u32 = 25;
+    let mut path: Option<String> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next(){
+        match arg.as_str(){
+            "--profile" => profile = iter.next().cloned(),
+            "--frames" => frames = match iter.next().and_then(|x| x.parse().ok()){
+                Some(x) => x,
+                None => {error!("--frames expects a number"); return}
+            },
+            "--seconds" => seconds = match iter.next().and_then(|x| x.parse().ok()){
+                Some(x) => Some(x),
+                None => {error!("--seconds expects a number"); return}
+            },
+            "--step-ms" => step_ms = match iter.next().and_then(|x| x.parse().ok()){
+                Some(x) if x > 0 => x,
+                _ => {error!("--step-ms expects a positive number"); return}
+            },
+            _ => path = Some(arg.clone())
+        }
+    }
+    let (profile, path) = match (profile, path){
+        (Some(x), Some(y)) => (x, y),
+        _ => {error!("Usage: record --profile <name> [--frames <n> | --seconds <s>] [--step-ms <ms>] <out.png|out.gif|out.fseq>"); return}
+    };
+    // fseq headers keep the step in a single byte
+    let fseq = path.to_lowercase().ends_with(".fseq");
+    if fseq && step_ms > u8::MAX as u32{
+        error!("--step-ms can be at most 255 for fseq files");
+        return;
+    }
+    if let Some(x) = seconds{
+        frames = (x * 1000.0 / step_ms as f64).round() as usize;
+    }
+
+    if system.create_instance(profile.clone(), "record".to_string()).is_err(){
+        error!("Failed to create an instance of {}", profile);
+        return;
+    }
+    system.get_instance_mut(profile.clone(), "record".to_string()).unwrap().set_on(true);
+    if fseq{
+        system.add_output(Box::new(FseqRecorder::new(path.clone(), step_ms as u8)));
+    }else{
+        let mut recorder = FrameRecorder::from_path(path.clone());
+        recorder.set_frame_delay(step_ms);
+        system.add_output(Box::new(recorder));
+    }
+
+    for _ in 0..frames{
+        system.update();
+    }
+    info!("Recorded {} frames of {} to {}", frames, profile, path);
+}
+
 fn main(){
     #[cfg(not(debug_assertions))]
     env::set_var("RUST_LOG", "INFO");
@@ -15,18 +110,29 @@ fn main(){
 
     info!("Starting");
 
+    let args: Vec<String> = env::args().skip(1).collect();
     let profiles_dir = "profiles".to_string();
 
     let mut system = System::new(profiles_dir);
     system.init();
 
-    _ = system.create_instance("basic-pattern".to_string(), "Basic Pattern Test".to_string());
-    system.get_instance_mut("basic-pattern".to_string(), "Basic Pattern Test".to_string()).unwrap().set_on(true);
     _ = system.add_light(LightStrip::new_enum("main strip".to_string(), 0, 300, RgbLight::default_enum()));
 
-    for _ in 0..500{
-        system.update();
+    if args.first().map(|x| x.as_str()) != Some("record"){
+        _ = system.create_instance("basic-pattern".to_string(), "Basic Pattern Test".to_string());
+        system.get_instance_mut("basic-pattern".to_string(), "Basic Pattern Test".to_string()).unwrap().set_on(true);
+    }
+
+    match args.first().map(|x| x.as_str()){
+        Some("stream") => stream(&mut system, &args[1..]),
+        Some("record") => record(&mut system, &args[1..]),
+        _ => {
+            for _ in 0..500{
+                system.update();
+            }
+        }
     }
+    system.clear_outputs();
 
 
     info!("Closing")